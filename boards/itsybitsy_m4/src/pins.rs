@@ -17,11 +17,11 @@ define_pins!(
     struct Pins,
     target_device: target_device,
 
-    /// Pin A0 (analog). Can act as a true analog output as it has a DAC (which is
-    /// currently not supported by this hal) as well as input.
+    /// Pin A0 (analog). Can act as a true analog output as it has a DAC
+    /// (see `hal::dac`) as well as input.
     pin a0 = a2,
-    /// Pin A1 (analog). Can act as a true analog output as it has a DAC (which is
-    /// currently not supported by this hal) as well as input.
+    /// Pin A1 (analog). Can act as a true analog output as it has a DAC
+    /// (see `hal::dac`) as well as input.
     pin a1 = a5,
     /// Pin A2 (analog)
     pin a2 = b8,
@@ -106,3 +106,30 @@ pub fn esp_uart<F: Into<Hertz>>(
         (esp_rx.into_pad(port), esp_tx.into_pad(port)),
     )
 }
+
+/// UART connected to the ESP32 Wi-Fi co-processor with hardware flow
+/// control; transmission pauses while the ESP32 deasserts its RTS line
+pub fn esp_uart_flow_control<F: Into<Hertz>>(
+    clocks: &mut GenericClockController,
+    baud: F,
+    sercom4: pac::SERCOM4,
+    mclk: &mut pac::MCLK,
+    esp_rx: gpio::Pb13<Input<Floating>>,
+    esp_tx: gpio::Pb12<Input<Floating>>,
+    esp_rts: gpio::Pa15<Input<Floating>>,
+    port: &mut Port,
+) -> UART4<
+        hal::sercom::Sercom4Pad1<gpio::Pb13<PfC>>,
+        hal::sercom::Sercom4Pad0<gpio::Pb12<PfC>>, (),
+        hal::sercom::Sercom4Pad3<gpio::Pa15<gpio::PfD>>
+    > {
+    let gclk0 = clocks.gclk0();
+
+    UART4::new(
+        &clocks.sercom4_core(&gclk0).unwrap(),
+        baud.into(),
+        sercom4,
+        mclk,
+        (esp_rx.into_pad(port), esp_tx.into_pad(port), esp_rts.into_pad(port)),
+    )
+}
@@ -32,8 +32,8 @@ define_pins!(
 
     /// D0/A0/DAC, EXTERNAL_INT_2
     /// Can act as a true analog output
-    /// as it has a DAC (which is not currently supported
-    /// by this hal) as well as input.
+    /// as it has a DAC
+    /// (see `hal::dac`) as well as input.
     pin d0 = a2,
     /// D1/A1/AREF, EXTERNAL_INT_3
     pin d1 = a3,
@@ -104,6 +104,60 @@ pub trait IntoFunction<T> {
     fn into_function(self, port: &mut Port) -> T;
 }
 
+/// Marks the peripheral function modes, which are the only pin modes
+/// that implement `PeripheralPin`.  You should not implement this trait
+/// for yourself.
+pub trait PeripheralFunction {}
+
+impl PeripheralFunction for PfA {}
+impl PeripheralFunction for PfB {}
+impl PeripheralFunction for PfC {}
+impl PeripheralFunction for PfD {}
+impl PeripheralFunction for PfE {}
+impl PeripheralFunction for PfF {}
+impl PeripheralFunction for PfG {}
+impl PeripheralFunction for PfH {}
+#[cfg(feature = "samd51")]
+impl PeripheralFunction for PfI {}
+#[cfg(feature = "samd51")]
+impl PeripheralFunction for PfJ {}
+#[cfg(feature = "samd51")]
+impl PeripheralFunction for PfK {}
+#[cfg(feature = "samd51")]
+impl PeripheralFunction for PfL {}
+#[cfg(feature = "samd51")]
+impl PeripheralFunction for PfM {}
+#[cfg(feature = "samd51")]
+impl PeripheralFunction for PfN {}
+
+/// The configuration of a pin saved by `PeripheralPin::detach`, which
+/// `PeripheralPin::attach` restores
+pub struct SavedPinConfig {
+    pincfg: u8,
+    dir: bool,
+    out: bool,
+}
+
+/// Direct control of a pin that is routed to a peripheral, emulating an
+/// open drain output.  Peripheral drivers use it to bit-bang a bus that
+/// their hardware can not recover, such as an I2C bus held low by a
+/// slave.  You should not implement this trait for yourself; only the
+/// implementations in the gpio and sercom modules make sense.
+pub trait PeripheralPin {
+    /// Take the pin from its peripheral and stop driving it, returning
+    /// the configuration to restore with `attach`
+    fn detach(&mut self) -> SavedPinConfig;
+    /// Hand the pin back to its peripheral, restoring the configuration
+    /// saved by `detach`
+    fn attach(&mut self, saved: SavedPinConfig);
+    /// Drive the detached pin low
+    fn drive_low(&mut self);
+    /// Stop driving the detached pin, letting the pull up raise it
+    fn release(&mut self);
+    /// Returns true if the detached pin is high
+    fn level(&self) -> bool;
+}
+
 // rustfmt wants to keep indenting the nested macro on each run,
 // so disable it for this whole block :-/
 #[cfg_attr(rustfmt, rustfmt_skip)]
@@ -307,6 +361,76 @@ macro_rules! pin {
             }
         }
 
+        impl<MODE: PeripheralFunction> PeripheralPin for $PinType<MODE> {
+            fn detach(&mut self) -> SavedPinConfig {
+                // The pin owns its PINCFG register and bits, so borrowing
+                // the port for them does not alias another owner
+                let mut port = Port { _0: () };
+                let saved = unsafe {
+                    SavedPinConfig {
+                        pincfg: port.$pincfg()[$pin_no].read().bits(),
+                        // Reading DIRSET and OUTSET returns DIR and OUT
+                        dir: (*PORT::ptr()).$dirset.read().bits() & (1 << $pin_no) != 0,
+                        out: (*PORT::ptr()).$outset.read().bits() & (1 << $pin_no) != 0,
+                    }
+                };
+
+                self.release();
+                unsafe {
+                    (*PORT::ptr()).$outclr.write(|bits| {
+                        bits.bits(1 << $pin_no);
+                        bits
+                    });
+                }
+                port.$pincfg()[$pin_no].modify(|_, bits| {
+                    bits.pmuxen().clear_bit();
+                    bits.inen().set_bit()
+                });
+                saved
+            }
+
+            fn attach(&mut self, saved: SavedPinConfig) {
+                let mut port = Port { _0: () };
+                unsafe {
+                    if saved.out {
+                        (*PORT::ptr()).$outset.write(|bits| {
+                            bits.bits(1 << $pin_no);
+                            bits
+                        });
+                    }
+                    if saved.dir {
+                        (*PORT::ptr()).$dirset.write(|bits| {
+                            bits.bits(1 << $pin_no);
+                            bits
+                        });
+                    }
+                    port.$pincfg()[$pin_no].write(|bits| bits.bits(saved.pincfg));
+                }
+            }
+
+            fn drive_low(&mut self) {
+                unsafe {
+                    (*PORT::ptr()).$dirset.write(|bits| {
+                        bits.bits(1 << $pin_no);
+                        bits
+                    });
+                }
+            }
+
+            fn release(&mut self) {
+                unsafe {
+                    (*PORT::ptr()).$dirclr.write(|bits| {
+                        bits.bits(1 << $pin_no);
+                        bits
+                    });
+                }
+            }
+
+            fn level(&self) -> bool {
+                unsafe { (((*PORT::ptr()).$in.read().bits()) & (1 << $pin_no)) != 0 }
+            }
+        }
+
         impl<MODE> $PinType<Output<MODE>> {
             /// Toggle the logic level of the pin; if it is currently
             /// high, set it low and vice versa.
@@ -29,6 +29,28 @@ impl<PIN> $PadType<PIN> {
     }
 }
 
+impl<PIN: $crate::gpio::PeripheralPin> $crate::gpio::PeripheralPin for $PadType<PIN> {
+    fn detach(&mut self) -> $crate::gpio::SavedPinConfig {
+        self.0.detach()
+    }
+
+    fn attach(&mut self, saved: $crate::gpio::SavedPinConfig) {
+        self.0.attach(saved)
+    }
+
+    fn drive_low(&mut self) {
+        self.0.drive_low()
+    }
+
+    fn release(&mut self) {
+        self.0.release()
+    }
+
+    fn level(&self) -> bool {
+        self.0.level()
+    }
+}
+
 $(
     impl<MODE> PadPin<$PadType<gpio::$PinType<gpio::$Pf>>> for gpio::$PinType<MODE> {
         fn into_pad(self, port: &mut Port) -> $PadType<gpio::$PinType<gpio::$Pf>> {
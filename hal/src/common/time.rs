@@ -16,6 +16,18 @@ pub struct KiloHertz(pub u32);
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub struct MegaHertz(pub u32);
 
+/// Seconds
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Seconds(pub u32);
+
+/// Milliseconds
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Milliseconds(pub u32);
+
+/// Microseconds
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Microseconds(pub u32);
+
 /// Extension trait that adds convenience methods to the `u32` type
 pub trait U32Ext {
     /// Wrap in `Bps`
@@ -29,6 +41,15 @@ pub trait U32Ext {
 
     /// Wrap in `MegaHertz`
     fn mhz(self) -> MegaHertz;
+
+    /// Wrap in `Seconds`
+    fn s(self) -> Seconds;
+
+    /// Wrap in `Milliseconds`
+    fn ms(self) -> Milliseconds;
+
+    /// Wrap in `Microseconds`
+    fn us(self) -> Microseconds;
 }
 
 impl U32Ext for u32 {
@@ -47,6 +68,18 @@ impl U32Ext for u32 {
     fn mhz(self) -> MegaHertz {
         MegaHertz(self)
     }
+
+    fn s(self) -> Seconds {
+        Seconds(self)
+    }
+
+    fn ms(self) -> Milliseconds {
+        Milliseconds(self)
+    }
+
+    fn us(self) -> Microseconds {
+        Microseconds(self)
+    }
 }
 
 impl Into<Hertz> for KiloHertz {
@@ -66,3 +99,108 @@ impl Into<KiloHertz> for MegaHertz {
         KiloHertz(self.0 * 1_000)
     }
 }
+
+impl Into<Milliseconds> for Seconds {
+    fn into(self) -> Milliseconds {
+        Milliseconds(self.0 * 1_000)
+    }
+}
+
+impl Into<Microseconds> for Seconds {
+    fn into(self) -> Microseconds {
+        Microseconds(self.0 * 1_000_000)
+    }
+}
+
+impl Into<Microseconds> for Milliseconds {
+    fn into(self) -> Microseconds {
+        Microseconds(self.0 * 1_000)
+    }
+}
+
+/// A point in time, measured in ticks of a free running 32-bit counter.
+/// Instants are compared taking the counter wrap around into account, so
+/// they are only meaningfully ordered within half of the counter range.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Instant(u32);
+
+/// A span of time, measured in ticks of a free running 32-bit counter
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct Duration(u32);
+
+impl Instant {
+    /// Create an instant from a raw counter value
+    pub fn from_ticks(ticks: u32) -> Self {
+        Instant(ticks)
+    }
+
+    /// Returns the raw counter value
+    pub fn ticks(self) -> u32 {
+        self.0
+    }
+
+    /// Returns the time elapsed from `earlier` to this instant
+    pub fn duration_since(self, earlier: Instant) -> Duration {
+        Duration(self.0.wrapping_sub(earlier.0))
+    }
+}
+
+impl Ord for Instant {
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        (self.0.wrapping_sub(other.0) as i32).cmp(&0)
+    }
+}
+
+impl PartialOrd for Instant {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl core::ops::Add<Duration> for Instant {
+    type Output = Instant;
+
+    fn add(self, rhs: Duration) -> Instant {
+        Instant(self.0.wrapping_add(rhs.0))
+    }
+}
+
+impl core::ops::AddAssign<Duration> for Instant {
+    fn add_assign(&mut self, rhs: Duration) {
+        *self = *self + rhs;
+    }
+}
+
+impl core::ops::Sub<Duration> for Instant {
+    type Output = Instant;
+
+    fn sub(self, rhs: Duration) -> Instant {
+        Instant(self.0.wrapping_sub(rhs.0))
+    }
+}
+
+impl core::ops::Sub for Instant {
+    type Output = Duration;
+
+    fn sub(self, rhs: Instant) -> Duration {
+        self.duration_since(rhs)
+    }
+}
+
+impl Duration {
+    /// Create a duration from a number of counter ticks
+    pub fn from_ticks(ticks: u32) -> Self {
+        Duration(ticks)
+    }
+
+    /// Returns the number of counter ticks
+    pub fn ticks(self) -> u32 {
+        self.0
+    }
+}
+
+impl From<Duration> for u32 {
+    fn from(duration: Duration) -> u32 {
+        duration.0
+    }
+}
@@ -0,0 +1,306 @@
+//! Working with the Digital-to-Analog Converter (DAC).
+//!
+//! The DAC converts a 10-bit value into a voltage between 0V and the
+//! selected reference on VOUT, which is PA02.  The output can also be
+//! routed internally to the analog comparators and the ADC.
+//!
+//! Besides writing values directly with `write`, conversions can be
+//! paced by an event: with the start event enabled, each event moves the
+//! value in the data buffer into the converter.  The data buffer can be
+//! refilled by the DMAC, so that a timer routed through the event system
+//! plays back a buffer of samples without involving the CPU.
+//!
+//! ```no_run
+//! let gclk0 = clocks.gclk0();
+//! let dac_clock = clocks.dac(&gclk0).unwrap();
+//! let vout = pins.a0.into_function_b(&mut pins.port);
+//! let mut dac = Dac::init(peripherals.DAC, &mut peripherals.PM, &dac_clock, vout, Reference::Avcc);
+//! dac.write(MAX_VALUE / 2);
+//! ```
+use crate::clock::DacClock;
+use crate::dmac::{self, BeatSize, Channel, ChannelId, Priority, TriggerAction, TriggerSource};
+use crate::gpio::{Pa2, PfB};
+use crate::target_device::{DAC, PM};
+use crate::time::Hertz;
+
+/// The largest value that can be converted
+pub const MAX_VALUE: u16 = 0x3ff;
+
+/// The voltage reference, which sets the output for `MAX_VALUE`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reference {
+    /// The internal 1.0V reference
+    Internal1V,
+    /// The analog supply voltage
+    Avcc,
+    /// The external reference on VREFA
+    External,
+}
+
+/// The DAC interrupts that can be enabled or inspected
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Interrupt {
+    /// The data buffer is empty and may be written
+    Empty,
+    /// A start event occurred while the data buffer was empty
+    Underrun,
+}
+
+/// Errors starting a DMA write
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error {
+    /// The buffer is empty, or longer than the 65535 samples a DMA
+    /// descriptor can move
+    BufferLength,
+}
+
+/// A write of a buffer of samples driven by the DMAC, returned by
+/// `Dac::dma_write`.
+///
+/// Poll the transfer with `poll` or block on it with `wait`, then use
+/// `free` to get back the DAC, the DMA channel and the buffer.
+pub struct DacDmaWrite<CH> {
+    dac: Dac,
+    channel: Channel<CH>,
+    buffer: &'static [u16],
+}
+
+/// `Dac` encapsulates the DAC hardware and its output pin.
+pub struct Dac {
+    dac: DAC,
+    vout: Pa2<PfB>,
+    freq: Hertz,
+}
+
+impl Dac {
+    /// Configure the DAC to drive `vout`, using the `reference` voltage,
+    /// and enable it.  The output is initially 0V.
+    pub fn init(
+        dac: DAC,
+        pm: &mut PM,
+        clock: &DacClock,
+        vout: Pa2<PfB>,
+        reference: Reference,
+    ) -> Self {
+        pm.apbcmask.modify(|_, w| w.dac_().set_bit());
+
+        dac.ctrla.write(|w| w.swrst().set_bit());
+        while dac.ctrla.read().swrst().bit_is_set() || dac.status.read().syncbusy().bit_is_set() {}
+
+        dac.ctrlb.write(|w| {
+            match reference {
+                Reference::Internal1V => w.refsel().int1v(),
+                Reference::Avcc => w.refsel().avcc(),
+                Reference::External => w.refsel().vrefp(),
+            };
+            w.eoen().set_bit()
+        });
+        dac.data.write(|w| unsafe { w.bits(0) });
+
+        let mut dac = Self {
+            dac,
+            vout,
+            freq: clock.freq(),
+        };
+        dac.enable();
+        dac
+    }
+
+    /// Returns the frequency of the DAC clock
+    pub fn clock_freq(&self) -> Hertz {
+        self.freq
+    }
+
+    fn sync(&self) {
+        while self.dac.status.read().syncbusy().bit_is_set() {}
+    }
+
+    fn enable(&mut self) {
+        self.dac.ctrla.modify(|_, w| w.enable().set_bit());
+        self.sync();
+    }
+
+    /// Run `f` with the DAC disabled, as required to change the
+    /// enable-protected configuration
+    fn disabled<F: FnOnce(&DAC)>(&mut self, f: F) {
+        self.dac.ctrla.modify(|_, w| w.enable().clear_bit());
+        self.sync();
+        f(&self.dac);
+        self.enable();
+    }
+
+    /// Select the voltage reference
+    pub fn set_reference(&mut self, reference: Reference) {
+        self.disabled(|dac| {
+            dac.ctrlb.modify(|_, w| match reference {
+                Reference::Internal1V => w.refsel().int1v(),
+                Reference::Avcc => w.refsel().avcc(),
+                Reference::External => w.refsel().vrefp(),
+            })
+        });
+    }
+
+    /// Route the output to the analog comparators and the ADC, in
+    /// addition to VOUT
+    pub fn set_internal_output(&mut self, enabled: bool) {
+        self.disabled(|dac| dac.ctrlb.modify(|_, w| w.ioen().bit(enabled)));
+    }
+
+    /// Take written values as left adjusted, so that the 10 most
+    /// significant bits of a 16-bit value are converted
+    pub fn set_left_adjusted(&mut self, left_adjusted: bool) {
+        self.disabled(|dac| dac.ctrlb.modify(|_, w| w.leftadj().bit(left_adjusted)));
+    }
+
+    /// Disable the voltage pump, which lowers the power consumption when
+    /// the supply voltage is above 2.5V
+    pub fn set_voltage_pump_disabled(&mut self, disabled: bool) {
+        self.disabled(|dac| dac.ctrlb.modify(|_, w| w.vpd().bit(disabled)));
+    }
+
+    /// Keep the DAC running in standby sleep mode
+    pub fn set_run_in_standby(&mut self, run_in_standby: bool) {
+        self.dac
+            .ctrla
+            .modify(|_, w| w.runstdby().bit(run_in_standby));
+        self.sync();
+    }
+
+    /// Convert `value`, which must not exceed `MAX_VALUE` unless the DAC
+    /// is left adjusted
+    pub fn write(&mut self, value: u16) {
+        self.sync();
+        self.dac.data.write(|w| unsafe { w.bits(value) });
+    }
+
+    /// Write `value` to the data buffer, to be converted on the next
+    /// start event
+    pub fn write_buffer(&mut self, value: u16) {
+        self.sync();
+        self.dac.databuf.write(|w| unsafe { w.bits(value) });
+    }
+
+    /// Start a conversion of the data buffer on each incoming event.
+    /// This method only configures the DAC to accept the event; the event
+    /// must be routed to the DAC through the event system.
+    pub fn enable_start_event(&mut self) {
+        self.disabled(|dac| dac.evctrl.modify(|_, w| w.startei().set_bit()));
+    }
+
+    /// Stop starting conversions on incoming events
+    pub fn disable_start_event(&mut self) {
+        self.disabled(|dac| dac.evctrl.modify(|_, w| w.startei().clear_bit()));
+    }
+
+    /// Generate an event when the data buffer is empty
+    pub fn enable_empty_event(&mut self) {
+        self.disabled(|dac| dac.evctrl.modify(|_, w| w.emptyeo().set_bit()));
+    }
+
+    /// Stop generating an event when the data buffer is empty
+    pub fn disable_empty_event(&mut self) {
+        self.disabled(|dac| dac.evctrl.modify(|_, w| w.emptyeo().clear_bit()));
+    }
+
+    /// Enable the interrupt generation for `interrupt`.
+    /// This method only configures the DAC to raise the interrupt;
+    /// it does not configure the interrupt controller or define an
+    /// interrupt handler.
+    pub fn enable_interrupt(&mut self, interrupt: Interrupt) {
+        self.dac.intenset.write(|w| match interrupt {
+            Interrupt::Empty => w.empty().set_bit(),
+            Interrupt::Underrun => w.underrun().set_bit(),
+        });
+    }
+
+    /// Disable the interrupt generation for `interrupt`
+    pub fn disable_interrupt(&mut self, interrupt: Interrupt) {
+        self.dac.intenclr.write(|w| match interrupt {
+            Interrupt::Empty => w.empty().set_bit(),
+            Interrupt::Underrun => w.underrun().set_bit(),
+        });
+    }
+
+    /// Returns true if the flag of `interrupt` is set
+    pub fn is_interrupt_set(&self, interrupt: Interrupt) -> bool {
+        let flags = self.dac.intflag.read();
+        match interrupt {
+            Interrupt::Empty => flags.empty().bit_is_set(),
+            Interrupt::Underrun => flags.underrun().bit_is_set(),
+        }
+    }
+
+    /// Clear the flag of `interrupt`
+    pub fn clear_interrupt(&mut self, interrupt: Interrupt) {
+        // Writing a 1 clears the flag
+        self.dac.intflag.write(|w| match interrupt {
+            Interrupt::Empty => w.empty().set_bit(),
+            Interrupt::Underrun => w.underrun().set_bit(),
+        });
+    }
+
+    /// Write `buffer` to the data buffer using the `channel` DMA
+    /// channel, one sample each time the data buffer empties.  Enable
+    /// the start event to pace the conversions, for example with the
+    /// overflow event of a timer.  The returned handle owns the DAC until
+    /// the transfer is freed.  If the buffer is empty or longer than
+    /// 65535 samples, returns `Error::BufferLength` along with the
+    /// untouched DAC and channel.
+    pub fn dma_write<CH: ChannelId>(
+        self,
+        mut channel: Channel<CH>,
+        buffer: &'static [u16],
+    ) -> Result<DacDmaWrite<CH>, (Error, Self, Channel<CH>)> {
+        if buffer.is_empty() || buffer.len() > u16::MAX as usize {
+            return Err((Error::BufferLength, self, channel));
+        }
+        let databuf = &self.dac.databuf as *const _ as u32;
+
+        channel.configure(TriggerSource::DAC_EMPTY, TriggerAction::Beat, Priority::Level0);
+        channel
+            .descriptor()
+            .configure(
+                BeatSize::HalfWord,
+                buffer.len() as u16,
+                buffer.as_ptr() as u32,
+                true,
+                databuf,
+                false,
+            )
+            .link(None);
+        channel.start();
+
+        Ok(DacDmaWrite {
+            dac: self,
+            channel,
+            buffer,
+        })
+    }
+
+    /// Disable the DAC and release the DAC and its output pin
+    pub fn free(self, pm: &mut PM) -> (DAC, Pa2<PfB>) {
+        self.dac.ctrla.write(|w| w.swrst().set_bit());
+        while self.dac.ctrla.read().swrst().bit_is_set() {}
+        pm.apbcmask.modify(|_, w| w.dac_().clear_bit());
+        (self.dac, self.vout)
+    }
+}
+
+impl<CH: ChannelId> DacDmaWrite<CH> {
+    /// Returns `Ok` once every sample has been written to the data buffer
+    pub fn poll(&mut self) -> nb::Result<(), dmac::Error> {
+        self.channel.wait()
+    }
+
+    /// Block until the transfer completes
+    pub fn wait(&mut self) -> Result<(), dmac::Error> {
+        nb::block!(self.poll())
+    }
+
+    /// Stop the transfer if it is still running, and release the DAC,
+    /// the DMA channel and the buffer
+    pub fn free(mut self) -> (Dac, Channel<CH>, &'static [u16]) {
+        self.channel.stop();
+        (self.dac, self.channel, self.buffer)
+    }
+}
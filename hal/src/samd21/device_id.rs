@@ -0,0 +1,137 @@
+//! Serial number and device identification
+// See 10.3.3 Serial Number, and 13.13.17 DID in the datasheet
+
+use crate::target_device::DSU;
+use core::ptr;
+
+/// The addresses of the four words of the 128-bit serial number
+const SERIAL_NUMBER_ADDRS: [u32; 4] = [0x0080_a00c, 0x0080_a040, 0x0080_a044, 0x0080_a048];
+
+/// The DSU series of the SAMD21 parts
+const SERIES_SAMD21: u8 = 1;
+
+/// Returns the 128-bit serial number, which is unique to each device
+pub fn serial_number_words() -> [u32; 4] {
+    let mut words = [0; 4];
+    for (word, addr) in words.iter_mut().zip(SERIAL_NUMBER_ADDRS.iter()) {
+        *word = unsafe { ptr::read(*addr as *const u32) };
+    }
+    words
+}
+
+/// Returns the serial number as bytes, most significant byte first
+pub fn serial_number() -> [u8; 16] {
+    let mut bytes = [0; 16];
+    for (chunk, word) in bytes.chunks_mut(4).zip(serial_number_words().iter()) {
+        chunk.copy_from_slice(&word.to_be_bytes());
+    }
+    bytes
+}
+
+/// Returns the serial number as 32 upper case hexadecimal ASCII digits,
+/// suitable for a USB serial number string descriptor
+pub fn serial_number_hex() -> [u8; 32] {
+    const DIGITS: &[u8; 16] = b"0123456789ABCDEF";
+    let mut hex = [0; 32];
+    for (i, byte) in serial_number().iter().enumerate() {
+        hex[i * 2] = DIGITS[(byte >> 4) as usize];
+        hex[i * 2 + 1] = DIGITS[(byte & 0xf) as usize];
+    }
+    hex
+}
+
+/// The SAMD21 parts, as identified by the DEVSEL field of DID
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Part {
+    Samd21j18a,
+    Samd21j17a,
+    Samd21j16a,
+    Samd21j15a,
+    Samd21g18a,
+    Samd21g17a,
+    Samd21g16a,
+    Samd21g15a,
+    Samd21e18a,
+    Samd21e17a,
+    Samd21e16a,
+    Samd21e15a,
+}
+
+impl Part {
+    fn from_devsel(devsel: u8) -> Option<Part> {
+        Some(match devsel {
+            0x00 => Part::Samd21j18a,
+            0x01 => Part::Samd21j17a,
+            0x02 => Part::Samd21j16a,
+            0x03 => Part::Samd21j15a,
+            0x05 => Part::Samd21g18a,
+            0x06 => Part::Samd21g17a,
+            0x07 => Part::Samd21g16a,
+            0x08 => Part::Samd21g15a,
+            0x0a => Part::Samd21e18a,
+            0x0b => Part::Samd21e17a,
+            0x0c => Part::Samd21e16a,
+            0x0d => Part::Samd21e15a,
+            _ => return None,
+        })
+    }
+}
+
+/// The part selected by the crate features
+#[cfg(feature = "samd21e18a")]
+pub const TARGET_PART: Part = Part::Samd21e18a;
+#[cfg(feature = "samd21g18a")]
+pub const TARGET_PART: Part = Part::Samd21g18a;
+#[cfg(feature = "samd21j18a")]
+pub const TARGET_PART: Part = Part::Samd21j18a;
+
+/// The decoded contents of the DSU Device Identification register
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceId {
+    /// The processor; 1 is a Cortex-M0+
+    pub processor: u8,
+    /// The product family; 0 is the SAM D family
+    pub family: u8,
+    /// The product series; 1 is the SAMD21
+    pub series: u8,
+    /// The die number
+    pub die: u8,
+    /// The die revision; 0 is revision A
+    pub revision: u8,
+    /// The device variant within the series
+    pub devsel: u8,
+}
+
+impl DeviceId {
+    /// Read the Device Identification register
+    pub fn read(dsu: &DSU) -> Self {
+        let did = dsu.did.read();
+        DeviceId {
+            processor: did.processor().bits(),
+            family: did.family().bits(),
+            series: did.series().bits(),
+            die: did.die().bits(),
+            revision: did.revision().bits(),
+            devsel: did.devsel().bits(),
+        }
+    }
+
+    /// Returns the die revision as a letter, starting from 'A'
+    pub fn revision_letter(&self) -> char {
+        (b'A' + self.revision) as char
+    }
+
+    /// Returns the part, if it is a known SAMD21 part
+    pub fn part(&self) -> Option<Part> {
+        if self.series != SERIES_SAMD21 {
+            return None;
+        }
+        Part::from_devsel(self.devsel)
+    }
+
+    /// Returns true if the silicon is the part selected by the crate
+    /// features
+    pub fn is_target_part(&self) -> bool {
+        self.part() == Some(TARGET_PART)
+    }
+}
@@ -0,0 +1,537 @@
+//! Working with the Direct Memory Access Controller (DMAC).
+//!
+//! The DMAC moves data between memory and peripherals without involving
+//! the CPU.  `DmaController::init` takes ownership of the DMAC, sets up
+//! the descriptor and write-back sections in SRAM, enables the controller
+//! and hands out one typed `Channel` per hardware channel.
+//!
+//! Each channel has a first `TransferDescriptor` living in the descriptor
+//! section; additional descriptors may be linked to it in order to chain
+//! several block transfers together.  Completion is signalled through the
+//! channel interrupt flags, which may either be polled with `wait` or used
+//! to trigger the `DMAC` interrupt.
+use core::marker::PhantomData;
+use core::ptr;
+use core::sync::atomic::{compiler_fence, Ordering};
+
+use crate::target_device::{DMAC, PM};
+use cortex_m::interrupt;
+use nb;
+
+/// The number of DMA channels implemented by the hardware.
+pub const NUM_CHANNELS: usize = 12;
+
+const BTCTRL_VALID: u16 = 1 << 0;
+const BTCTRL_BLOCKACT_SHIFT: u16 = 3;
+const BTCTRL_BEATSIZE_SHIFT: u16 = 8;
+const BTCTRL_SRCINC: u16 = 1 << 10;
+const BTCTRL_DSTINC: u16 = 1 << 11;
+
+/// The size of a single beat, which is the unit of data moved by
+/// one DMA bus access.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BeatSize {
+    Byte = 0,
+    HalfWord = 1,
+    Word = 2,
+}
+
+impl BeatSize {
+    /// Number of bytes moved by a single beat
+    pub fn bytes(self) -> u32 {
+        1 << (self as u32)
+    }
+}
+
+/// The action taken by the DMAC when a block transfer completes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BlockAction {
+    /// Disable the channel if this was the last block of the transfer
+    NoAct = 0,
+    /// Disable the channel if this was the last block and raise the
+    /// transfer complete interrupt
+    Interrupt = 1,
+    /// Suspend the channel
+    Suspend = 2,
+    /// Suspend the channel and raise the transfer complete interrupt
+    Both = 3,
+}
+
+/// The amount of data transferred for each trigger received by a channel.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TriggerAction {
+    /// One trigger is required for each block transfer
+    Block = 0,
+    /// One trigger is required for each beat transfer
+    Beat = 2,
+    /// One trigger is required for each transaction
+    Transaction = 3,
+}
+
+/// Channel priority level.  Higher levels are arbitrated first.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Priority {
+    Level0 = 0,
+    Level1 = 1,
+    Level2 = 2,
+    Level3 = 3,
+}
+
+/// Peripheral trigger sources for a DMA channel
+/// (Table 19-8 in the datasheet).
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TriggerSource {
+    /// Only software or event triggers
+    DISABLE = 0x00,
+    SERCOM0_RX = 0x01,
+    SERCOM0_TX = 0x02,
+    SERCOM1_RX = 0x03,
+    SERCOM1_TX = 0x04,
+    SERCOM2_RX = 0x05,
+    SERCOM2_TX = 0x06,
+    SERCOM3_RX = 0x07,
+    SERCOM3_TX = 0x08,
+    SERCOM4_RX = 0x09,
+    SERCOM4_TX = 0x0A,
+    SERCOM5_RX = 0x0B,
+    SERCOM5_TX = 0x0C,
+    TCC0_OVF = 0x0D,
+    TCC0_MC0 = 0x0E,
+    TCC0_MC1 = 0x0F,
+    TCC0_MC2 = 0x10,
+    TCC0_MC3 = 0x11,
+    TCC1_OVF = 0x12,
+    TCC1_MC0 = 0x13,
+    TCC1_MC1 = 0x14,
+    TCC2_OVF = 0x15,
+    TCC2_MC0 = 0x16,
+    TCC2_MC1 = 0x17,
+    TC3_OVF = 0x18,
+    TC3_MC0 = 0x19,
+    TC3_MC1 = 0x1A,
+    TC4_OVF = 0x1B,
+    TC4_MC0 = 0x1C,
+    TC4_MC1 = 0x1D,
+    TC5_OVF = 0x1E,
+    TC5_MC0 = 0x1F,
+    TC5_MC1 = 0x20,
+    TC6_OVF = 0x21,
+    TC6_MC0 = 0x22,
+    TC6_MC1 = 0x23,
+    TC7_OVF = 0x24,
+    TC7_MC0 = 0x25,
+    TC7_MC1 = 0x26,
+    ADC_RESRDY = 0x27,
+    DAC_EMPTY = 0x28,
+    I2S_RX_0 = 0x29,
+    I2S_RX_1 = 0x2A,
+    I2S_TX_0 = 0x2B,
+    I2S_TX_1 = 0x2C,
+}
+
+/// The channel interrupts that can be enabled or inspected.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Interrupt {
+    /// A bus error was detected or an invalid descriptor was fetched
+    TransferError,
+    /// The block transfer completed and the descriptor requested
+    /// an interrupt
+    TransferComplete,
+    /// The channel was suspended
+    Suspend,
+}
+
+impl Interrupt {
+    fn mask(self) -> u8 {
+        match self {
+            Interrupt::TransferError => 1 << 0,
+            Interrupt::TransferComplete => 1 << 1,
+            Interrupt::Suspend => 1 << 2,
+        }
+    }
+}
+
+/// Errors reported by a DMA channel
+#[derive(Debug)]
+pub enum Error {
+    /// A bus error occurred, or the DMAC fetched an invalid descriptor
+    TransferError,
+}
+
+/// A DMA transfer descriptor, laid out as expected by the DMAC hardware.
+///
+/// The first descriptor of each channel lives in the descriptor section
+/// managed by `DmaController` and is accessed via `Channel::descriptor`.
+/// Further descriptors may be linked onto it with `link`; these must
+/// live for the `'static` lifetime as the hardware reads them
+/// asynchronously.
+#[repr(C, align(16))]
+#[derive(Debug, Clone, Copy)]
+pub struct TransferDescriptor {
+    btctrl: u16,
+    btcnt: u16,
+    srcaddr: u32,
+    dstaddr: u32,
+    descaddr: u32,
+}
+
+impl Default for TransferDescriptor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TransferDescriptor {
+    /// Returns an empty, invalid descriptor.
+    pub const fn new() -> Self {
+        TransferDescriptor {
+            btctrl: 0,
+            btcnt: 0,
+            srcaddr: 0,
+            dstaddr: 0,
+            descaddr: 0,
+        }
+    }
+
+    /// Describe a block transfer of `count` beats of size `beat` from
+    /// `src` to `dst`.  When `src_inc` or `dst_inc` is set, the respective
+    /// address is incremented after each beat, as is needed when
+    /// accessing a buffer rather than a peripheral data register.
+    /// The descriptor is marked valid and raises the transfer complete
+    /// interrupt flag when the block is done.
+    pub fn configure(
+        &mut self,
+        beat: BeatSize,
+        count: u16,
+        src: u32,
+        src_inc: bool,
+        dst: u32,
+        dst_inc: bool,
+    ) -> &mut Self {
+        // The hardware expects incrementing addresses to point to the
+        // end of the block rather than the start (section 19.6.2.7).
+        let len = count as u32 * beat.bytes();
+        self.srcaddr = if src_inc { src + len } else { src };
+        self.dstaddr = if dst_inc { dst + len } else { dst };
+        self.btcnt = count;
+
+        let mut btctrl = BTCTRL_VALID
+            | (BlockAction::Interrupt as u16) << BTCTRL_BLOCKACT_SHIFT
+            | (beat as u16) << BTCTRL_BEATSIZE_SHIFT;
+        if src_inc {
+            btctrl |= BTCTRL_SRCINC;
+        }
+        if dst_inc {
+            btctrl |= BTCTRL_DSTINC;
+        }
+        self.btctrl = btctrl;
+        self
+    }
+
+    /// Set the action taken when the block described by this descriptor
+    /// has been transferred.
+    pub fn set_block_action(&mut self, action: BlockAction) -> &mut Self {
+        self.btctrl = (self.btctrl & !(0x3 << BTCTRL_BLOCKACT_SHIFT))
+            | (action as u16) << BTCTRL_BLOCKACT_SHIFT;
+        self
+    }
+
+    /// Link the next descriptor to be processed after this one.
+    /// Passing `None` terminates the transfer after this block.
+    pub fn link(&mut self, next: Option<&'static TransferDescriptor>) -> &mut Self {
+        self.descaddr = match next {
+            Some(desc) => desc as *const TransferDescriptor as u32,
+            None => 0,
+        };
+        self
+    }
+
+    /// Mark this descriptor as valid or invalid.  The DMAC raises a
+    /// transfer error if it fetches an invalid descriptor.
+    pub fn set_valid(&mut self, valid: bool) -> &mut Self {
+        if valid {
+            self.btctrl |= BTCTRL_VALID;
+        } else {
+            self.btctrl &= !BTCTRL_VALID;
+        }
+        self
+    }
+
+    /// Returns true if this descriptor is marked as valid
+    pub fn is_valid(&self) -> bool {
+        self.btctrl & BTCTRL_VALID != 0
+    }
+
+    /// Returns the number of beats in the block
+    pub fn count(&self) -> u16 {
+        self.btcnt
+    }
+}
+
+#[repr(C, align(16))]
+struct DescriptorSection([TransferDescriptor; NUM_CHANNELS]);
+
+// The DMAC reads the first descriptor of each channel from the descriptor
+// section, and stores the progress of suspended or running transfers in
+// the write-back section.  Both are only accessed through a `Channel`,
+// which can only be obtained from the single `DmaController` instance.
+static mut DESCRIPTORS: DescriptorSection =
+    DescriptorSection([TransferDescriptor::new(); NUM_CHANNELS]);
+static mut WRITEBACK: DescriptorSection =
+    DescriptorSection([TransferDescriptor::new(); NUM_CHANNELS]);
+
+/// `DmaController` encapsulates the DMAC hardware.
+pub struct DmaController {
+    dmac: DMAC,
+}
+
+impl DmaController {
+    /// Power on, reset and enable the DMAC, with all four priority
+    /// levels enabled.  Returns the controller along with its individual
+    /// channels.
+    pub fn init(dmac: DMAC, pm: &mut PM) -> (Self, Channels) {
+        pm.ahbmask.modify(|_, w| w.dmac_().set_bit());
+        pm.apbbmask.modify(|_, w| w.dmac_().set_bit());
+
+        // Disable and reset the controller
+        dmac.ctrl.modify(|_, w| w.dmaenable().clear_bit());
+        dmac.ctrl.modify(|_, w| w.swrst().set_bit());
+        while dmac.ctrl.read().swrst().bit_is_set() {}
+
+        unsafe {
+            dmac.baseaddr
+                .write(|w| w.baseaddr().bits(ptr::addr_of!(DESCRIPTORS) as u32));
+            dmac.wrbaddr
+                .write(|w| w.wrbaddr().bits(ptr::addr_of!(WRITEBACK) as u32));
+        }
+
+        dmac.ctrl.modify(|_, w| {
+            w.lvlen0().set_bit();
+            w.lvlen1().set_bit();
+            w.lvlen2().set_bit();
+            w.lvlen3().set_bit();
+            w.dmaenable().set_bit()
+        });
+
+        (Self { dmac }, Channels::new())
+    }
+
+    /// Returns the id of the lowest channel with a pending interrupt,
+    /// or `None` if no channel has an interrupt pending.  Useful to
+    /// dispatch from within the `DMAC` interrupt handler.
+    pub fn pending_channel(&self) -> Option<u8> {
+        let intstatus = self.dmac.intstatus.read().bits();
+        if intstatus == 0 {
+            None
+        } else {
+            Some(intstatus.trailing_zeros() as u8)
+        }
+    }
+
+    /// Disable the DMAC and release the peripheral.  The channels are
+    /// consumed to prove that no transfer can be started afterwards.
+    pub fn free(self, _channels: Channels) -> DMAC {
+        self.dmac.ctrl.modify(|_, w| w.dmaenable().clear_bit());
+        self.dmac
+    }
+}
+
+/// A marker trait implemented by the types that identify each of the
+/// DMA channels.
+pub trait ChannelId {
+    const ID: u8;
+}
+
+/// A single DMA channel.
+pub struct Channel<CH> {
+    _ch: PhantomData<CH>,
+}
+
+impl<CH: ChannelId> Channel<CH> {
+    /// Run `f` with the channel registers of this channel selected
+    fn with_regs<R, F: FnOnce(&crate::target_device::dmac::RegisterBlock) -> R>(&self, f: F) -> R {
+        interrupt::free(|_| {
+            // Safe because the channel registers are only accessed within
+            // a critical section with our channel id selected.
+            let dmac = unsafe { &*DMAC::ptr() };
+            dmac.chid.write(|w| unsafe { w.id().bits(CH::ID) });
+            f(dmac)
+        })
+    }
+
+    /// Returns the hardware id of this channel
+    pub fn id(&self) -> u8 {
+        CH::ID
+    }
+
+    /// Reset the channel and configure the peripheral trigger source,
+    /// the amount of data transferred per trigger, and the priority.
+    pub fn configure(&mut self, trigger: TriggerSource, action: TriggerAction, priority: Priority) {
+        self.with_regs(|dmac| {
+            dmac.chctrla.modify(|_, w| w.enable().clear_bit());
+            while dmac.chctrla.read().enable().bit_is_set() {}
+            dmac.chctrla.modify(|_, w| w.swrst().set_bit());
+            while dmac.chctrla.read().swrst().bit_is_set() {}
+
+            dmac.chctrlb.write(|w| unsafe {
+                w.trigsrc().bits(trigger as u8);
+                w.trigact().bits(action as u8);
+                w.lvl().bits(priority as u8)
+            });
+        });
+    }
+
+    /// Returns the first transfer descriptor of this channel.
+    pub fn descriptor(&mut self) -> &mut TransferDescriptor {
+        unsafe { &mut DESCRIPTORS.0[CH::ID as usize] }
+    }
+
+    /// Returns the write-back descriptor of this channel.  While a
+    /// transfer is suspended or after it has been stopped, this holds
+    /// the progress of the current block; `count` then returns the
+    /// number of beats that remained to be transferred.
+    pub fn writeback(&self) -> TransferDescriptor {
+        unsafe { WRITEBACK.0[CH::ID as usize] }
+    }
+
+    /// Enable the channel.  The transfer begins on the next trigger.
+    pub fn start(&mut self) {
+        // Make sure the descriptors are written before the DMAC reads them
+        compiler_fence(Ordering::SeqCst);
+        self.with_regs(|dmac| dmac.chctrla.modify(|_, w| w.enable().set_bit()));
+    }
+
+    /// Issue a software trigger to the channel
+    pub fn trigger(&mut self) {
+        let dmac = unsafe { &*DMAC::ptr() };
+        interrupt::free(|_| {
+            dmac.swtrigctrl
+                .modify(|r, w| unsafe { w.bits(r.bits() | 1 << CH::ID) });
+        });
+    }
+
+    /// Disable the channel, aborting any ongoing transfer
+    pub fn stop(&mut self) {
+        self.with_regs(|dmac| {
+            dmac.chctrla.modify(|_, w| w.enable().clear_bit());
+            while dmac.chctrla.read().enable().bit_is_set() {}
+        });
+        compiler_fence(Ordering::SeqCst);
+    }
+
+    /// Suspend the ongoing transfer
+    pub fn suspend(&mut self) {
+        self.with_regs(|dmac| dmac.chctrlb.modify(|_, w| w.cmd().suspend()));
+    }
+
+    /// Resume a suspended transfer
+    pub fn resume(&mut self) {
+        self.with_regs(|dmac| dmac.chctrlb.modify(|_, w| w.cmd().resume()));
+    }
+
+    /// Returns true while the channel is enabled and has not yet
+    /// completed its transfer
+    pub fn is_enabled(&self) -> bool {
+        self.with_regs(|dmac| dmac.chctrla.read().enable().bit_is_set())
+    }
+
+    /// Returns true while the channel is actively transferring data
+    pub fn is_busy(&self) -> bool {
+        self.with_regs(|dmac| dmac.chstatus.read().busy().bit_is_set())
+    }
+
+    /// Poll for the completion of the transfer.  This clears the
+    /// transfer complete flag once the transfer has finished.
+    pub fn wait(&mut self) -> nb::Result<(), Error> {
+        let flags = self.with_regs(|dmac| dmac.chintflag.read().bits());
+        if flags & Interrupt::TransferError.mask() != 0 {
+            self.clear_interrupt(Interrupt::TransferError);
+            return Err(nb::Error::Other(Error::TransferError));
+        }
+        if flags & Interrupt::TransferComplete.mask() != 0 {
+            self.clear_interrupt(Interrupt::TransferComplete);
+            compiler_fence(Ordering::SeqCst);
+            Ok(())
+        } else {
+            Err(nb::Error::WouldBlock)
+        }
+    }
+
+    /// Enable the interrupt generation for this channel.
+    /// This method only configures the DMAC to generate the interrupt;
+    /// it does not configure the interrupt controller or define an
+    /// interrupt handler.
+    pub fn enable_interrupt(&mut self, interrupt: Interrupt) {
+        self.with_regs(|dmac| {
+            dmac.chintenset
+                .write(|w| unsafe { w.bits(interrupt.mask()) })
+        });
+    }
+
+    /// Disables interrupt generation for this channel.
+    pub fn disable_interrupt(&mut self, interrupt: Interrupt) {
+        self.with_regs(|dmac| {
+            dmac.chintenclr
+                .write(|w| unsafe { w.bits(interrupt.mask()) })
+        });
+    }
+
+    /// Returns true if the given interrupt flag is set
+    pub fn is_interrupt_set(&self, interrupt: Interrupt) -> bool {
+        self.with_regs(|dmac| dmac.chintflag.read().bits() & interrupt.mask() != 0)
+    }
+
+    /// Clears the given interrupt flag
+    pub fn clear_interrupt(&mut self, interrupt: Interrupt) {
+        self.with_regs(|dmac| {
+            dmac.chintflag
+                .write(|w| unsafe { w.bits(interrupt.mask()) })
+        });
+    }
+}
+
+macro_rules! channels {
+    ($($Ch:ident: ($field:ident, $id:expr),)+) => {
+        $(
+/// Identifies the DMA channel with the matching number
+pub struct $Ch;
+
+impl ChannelId for $Ch {
+    const ID: u8 = $id;
+}
+        )+
+
+/// The individual channels of the DMAC
+pub struct Channels {
+    $(
+        pub $field: Channel<$Ch>,
+    )+
+}
+
+impl Channels {
+    fn new() -> Self {
+        Channels {
+            $(
+                $field: Channel { _ch: PhantomData },
+            )+
+        }
+    }
+}
+    };
+}
+
+channels! {
+    Ch0: (ch0, 0),
+    Ch1: (ch1, 1),
+    Ch2: (ch2, 2),
+    Ch3: (ch3, 3),
+    Ch4: (ch4, 4),
+    Ch5: (ch5, 5),
+    Ch6: (ch6, 6),
+    Ch7: (ch7, 7),
+    Ch8: (ch8, 8),
+    Ch9: (ch9, 9),
+    Ch10: (ch10, 10),
+    Ch11: (ch11, 11),
+}
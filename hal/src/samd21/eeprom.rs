@@ -0,0 +1,500 @@
+//! An emulated EEPROM stored in a region of the flash.
+//!
+//! `EmulatedEeprom` is a key/value store of 32-bit values.  The flash
+//! region is split into two sectors of one or more rows each.  Values are
+//! appended as records to the active sector, so that updating a value does
+//! not erase the flash; when the active sector is full, the latest value
+//! of each key is copied into the other sector, which then becomes active.
+//! This spreads the erase cycles evenly over the region.
+//!
+//! Each record carries a check value, and a sector only becomes active
+//! once all of its records have been copied along with the update that
+//! filled the old sector, so an update that is interrupted by a power
+//! failure leaves the previous value in place.
+//!
+//! The region must not be used by the program; reserve it at the end of
+//! the flash in the board `memory.x`, for example:
+//!
+//! ```text
+//! MEMORY
+//! {
+//!   FLASH (rx) : ORIGIN = 0x00000000 + 8K, LENGTH = 256K - 8K - 2K
+//!   RAM (xrw)  : ORIGIN = 0x20000000, LENGTH = 32K
+//! }
+//! _eeprom_start = ORIGIN(FLASH) + LENGTH(FLASH);
+//! ```
+//!
+//! ```no_run
+//! extern "C" {
+//!     static _eeprom_start: u32;
+//! }
+//! let mut nvm = Nvm::new(peripherals.NVMCTRL);
+//! let start = unsafe { &_eeprom_start as *const u32 as u32 };
+//! // Two sectors of four 256 byte rows each
+//! let mut eeprom = EmulatedEeprom::new(&mut nvm, start, 4).unwrap();
+//! let boots = eeprom.get(BOOT_COUNT).unwrap().unwrap_or(0);
+//! eeprom.set(BOOT_COUNT, boots + 1).unwrap();
+//! ```
+use crate::nvm::{self, Nvm};
+
+/// Marks the header of an active sector
+const MAGIC: u32 = 0x4545_5052;
+const HEADER_SIZE: u32 = 8;
+const RECORD_SIZE: u32 = 8;
+const ERASED: u32 = 0xffff_ffff;
+
+/// The key that marks an erased record, which cannot be stored
+pub const INVALID_KEY: u16 = 0xffff;
+
+/// Errors that may occur accessing the emulated EEPROM
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The region is not row aligned, is empty or lies outside the flash
+    InvalidRegion,
+    /// The key is `INVALID_KEY`
+    InvalidKey,
+    /// A sector cannot hold the latest value of every key
+    Full,
+    /// The NVM controller reported an error
+    Nvm(nvm::Error),
+}
+
+impl From<nvm::Error> for Error {
+    fn from(e: nvm::Error) -> Self {
+        Error::Nvm(e)
+    }
+}
+
+/// The flash operations the emulated EEPROM is built on.  `Nvm`
+/// implements them for the on-chip flash.
+pub trait Flash {
+    /// Returns the size of an erase row in bytes
+    fn row_size(&self) -> usize;
+    /// Returns the size of the flash in bytes
+    fn flash_size(&self) -> usize;
+    /// Erase the row starting at `address`
+    fn erase_row(&mut self, address: u32) -> Result<(), nvm::Error>;
+    /// Write `data` to erased flash starting at `address`
+    fn write(&mut self, address: u32, data: &[u32]) -> Result<(), nvm::Error>;
+    /// Read `data.len()` words starting at `address`
+    fn read(&self, address: u32, data: &mut [u32]) -> Result<(), nvm::Error>;
+}
+
+impl Flash for Nvm {
+    fn row_size(&self) -> usize {
+        Nvm::row_size(self)
+    }
+
+    fn flash_size(&self) -> usize {
+        Nvm::flash_size(self)
+    }
+
+    fn erase_row(&mut self, address: u32) -> Result<(), nvm::Error> {
+        Nvm::erase_row(self, address)
+    }
+
+    fn write(&mut self, address: u32, data: &[u32]) -> Result<(), nvm::Error> {
+        Nvm::write(self, address, data)
+    }
+
+    fn read(&self, address: u32, data: &mut [u32]) -> Result<(), nvm::Error> {
+        Nvm::read(self, address, data)
+    }
+}
+
+/// The check value protecting a record against partial writes
+fn check(key: u16, value: u32) -> u16 {
+    !(key ^ value as u16 ^ (value >> 16) as u16 ^ 0x5a5a)
+}
+
+/// `EmulatedEeprom` stores key/value pairs in a flash region while
+/// borrowing the `Nvm`.
+pub struct EmulatedEeprom<'a, F: Flash = Nvm> {
+    nvm: &'a mut F,
+    start: u32,
+    sector_size: u32,
+    active: u32,
+    sequence: u32,
+    /// The offset of the first free record in the active sector
+    next: u32,
+}
+
+impl<'a, F: Flash> EmulatedEeprom<'a, F> {
+    /// Open the emulated EEPROM in the region starting at `start`, which
+    /// must be row aligned and span two sectors of `rows_per_sector` rows.
+    /// An unformatted region is erased, and the recovery of an update
+    /// that was interrupted by a power failure is completed.
+    pub fn new(nvm: &'a mut F, start: u32, rows_per_sector: usize) -> Result<Self, Error> {
+        let row_size = nvm.row_size();
+        let sector_size = (row_size * rows_per_sector) as u32;
+        if rows_per_sector == 0
+            || start as usize % row_size != 0
+            || start as usize + 2 * sector_size as usize > nvm.flash_size()
+        {
+            return Err(Error::InvalidRegion);
+        }
+
+        let mut eeprom = EmulatedEeprom {
+            nvm,
+            start,
+            sector_size,
+            active: 0,
+            sequence: 0,
+            next: HEADER_SIZE,
+        };
+
+        match (eeprom.header(0)?, eeprom.header(1)?) {
+            (Some(first), Some(second)) => {
+                // A copy completed but the old sector was not yet erased
+                let (active, sequence) = if (second.wrapping_sub(first) as i32) > 0 {
+                    (1, second)
+                } else {
+                    (0, first)
+                };
+                eeprom.erase_sector(1 - active)?;
+                eeprom.active = active;
+                eeprom.sequence = sequence;
+            }
+            (Some(sequence), None) => {
+                eeprom.active = 0;
+                eeprom.sequence = sequence;
+            }
+            (None, Some(sequence)) => {
+                eeprom.active = 1;
+                eeprom.sequence = sequence;
+            }
+            (None, None) => {
+                eeprom.erase_sector(0)?;
+                eeprom.write_words(eeprom.sector_address(0), &[MAGIC, 0])?;
+            }
+        }
+        eeprom.next = eeprom.find_next()?;
+        Ok(eeprom)
+    }
+
+    /// Returns the number of records a sector can hold
+    pub fn capacity(&self) -> usize {
+        ((self.sector_size - HEADER_SIZE) / RECORD_SIZE) as usize
+    }
+
+    /// Returns the latest value stored for `key`
+    pub fn get(&self, key: u16) -> Result<Option<u32>, Error> {
+        Ok(self.latest(self.active, key)?.map(|(_, value)| value))
+    }
+
+    /// Store `value` for `key`.  Storing the current value again does not
+    /// write to the flash.  Returns `Error::Full`, keeping the previous
+    /// value, if a sector cannot hold the latest value of every key.
+    pub fn set(&mut self, key: u16, value: u32) -> Result<(), Error> {
+        if key == INVALID_KEY {
+            return Err(Error::InvalidKey);
+        }
+        if self.get(key)? == Some(value) {
+            return Ok(());
+        }
+        if self.next + RECORD_SIZE > self.sector_size {
+            return self.swap_sectors(key, value);
+        }
+        let address = self.sector_address(self.active) + self.next;
+        self.write_record(address, key, value)?;
+        self.next += RECORD_SIZE;
+        Ok(())
+    }
+
+    fn sector_address(&self, sector: u32) -> u32 {
+        self.start + sector * self.sector_size
+    }
+
+    fn read_words(&self, address: u32) -> Result<[u32; 2], Error> {
+        let mut words = [0; 2];
+        self.nvm.read(address, &mut words)?;
+        Ok(words)
+    }
+
+    fn write_words(&mut self, address: u32, words: &[u32; 2]) -> Result<(), Error> {
+        Ok(self.nvm.write(address, words)?)
+    }
+
+    fn write_record(&mut self, address: u32, key: u16, value: u32) -> Result<(), Error> {
+        self.write_words(address, &[key as u32 | (check(key, value) as u32) << 16, value])
+    }
+
+    fn erase_sector(&mut self, sector: u32) -> Result<(), Error> {
+        let row_size = self.nvm.row_size() as u32;
+        let address = self.sector_address(sector);
+        for row in 0..self.sector_size / row_size {
+            self.nvm.erase_row(address + row * row_size)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the sequence number of a sector with a valid header
+    fn header(&self, sector: u32) -> Result<Option<u32>, Error> {
+        let [magic, sequence] = self.read_words(self.sector_address(sector))?;
+        Ok(if magic == MAGIC && sequence != ERASED {
+            Some(sequence)
+        } else {
+            None
+        })
+    }
+
+    /// Returns the key and value of the record at `offset` in `sector`,
+    /// or `None` if the record is erased or was partially written
+    fn record(&self, sector: u32, offset: u32) -> Result<Option<(u16, u32)>, Error> {
+        let [tag, value] = self.read_words(self.sector_address(sector) + offset)?;
+        let key = tag as u16;
+        Ok(if key != INVALID_KEY && (tag >> 16) as u16 == check(key, value) {
+            Some((key, value))
+        } else {
+            None
+        })
+    }
+
+    /// Returns the offset and value of the latest record for `key`
+    fn latest(&self, sector: u32, key: u16) -> Result<Option<(u32, u32)>, Error> {
+        let mut latest = None;
+        let mut offset = HEADER_SIZE;
+        while offset < self.next {
+            if let Some((k, value)) = self.record(sector, offset)? {
+                if k == key {
+                    latest = Some((offset, value));
+                }
+            }
+            offset += RECORD_SIZE;
+        }
+        Ok(latest)
+    }
+
+    /// Returns the offset of the first erased record in the active sector
+    fn find_next(&self) -> Result<u32, Error> {
+        let address = self.sector_address(self.active);
+        let mut offset = HEADER_SIZE;
+        while offset < self.sector_size {
+            // Partially written records are skipped, not reused
+            if self.read_words(address + offset)? == [ERASED, ERASED] {
+                break;
+            }
+            offset += RECORD_SIZE;
+        }
+        Ok(offset)
+    }
+
+    /// Returns the key and value of the record at `offset` in the active
+    /// sector if it holds the latest value of its key
+    fn latest_record(&self, offset: u32) -> Result<Option<(u16, u32)>, Error> {
+        Ok(match self.record(self.active, offset)? {
+            Some((key, value)) if self.latest(self.active, key)?.map(|(o, _)| o) == Some(offset) => {
+                Some((key, value))
+            }
+            _ => None,
+        })
+    }
+
+    /// Copy the latest value of every key other than `key` into the
+    /// other sector, followed by `value` for `key`, and make it active.
+    /// Returns `Error::Full` without touching the flash if they do not fit.
+    fn swap_sectors(&mut self, key: u16, value: u32) -> Result<(), Error> {
+        let mut count = 1;
+        let mut offset = HEADER_SIZE;
+        while offset < self.next {
+            if let Some((k, _)) = self.latest_record(offset)? {
+                if k != key {
+                    count += 1;
+                }
+            }
+            offset += RECORD_SIZE;
+        }
+        if count > self.capacity() {
+            return Err(Error::Full);
+        }
+
+        let target = 1 - self.active;
+        let target_address = self.sector_address(target);
+        self.erase_sector(target)?;
+
+        let mut next = HEADER_SIZE;
+        let mut offset = HEADER_SIZE;
+        while offset < self.next {
+            if let Some((k, v)) = self.latest_record(offset)? {
+                if k != key {
+                    self.write_record(target_address + next, k, v)?;
+                    next += RECORD_SIZE;
+                }
+            }
+            offset += RECORD_SIZE;
+        }
+        self.write_record(target_address + next, key, value)?;
+        next += RECORD_SIZE;
+
+        // The header is written last, so that the target only becomes
+        // valid once every record, including the update, is in place
+        let sequence = self.sequence.wrapping_add(1);
+        self.write_words(target_address, &[MAGIC, sequence])?;
+        let old = self.active;
+        self.active = target;
+        self.sequence = sequence;
+        self.next = next;
+        self.erase_sector(old)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROW_SIZE: usize = 64;
+    const WORDS: usize = 2 * ROW_SIZE / 4;
+
+    /// A flash of two rows, one per sector.  Once `budget` words have
+    /// been written or rows erased, every operation fails as if the
+    /// power was lost, possibly half way through a record.
+    struct FakeFlash {
+        words: [u32; WORDS],
+        budget: usize,
+    }
+
+    impl FakeFlash {
+        fn new() -> Self {
+            FakeFlash {
+                words: [ERASED; WORDS],
+                budget: usize::MAX,
+            }
+        }
+
+        fn spend(&mut self) -> Result<(), nvm::Error> {
+            if self.budget == 0 {
+                return Err(nvm::Error::NvmError);
+            }
+            self.budget -= 1;
+            Ok(())
+        }
+    }
+
+    impl Flash for FakeFlash {
+        fn row_size(&self) -> usize {
+            ROW_SIZE
+        }
+
+        fn flash_size(&self) -> usize {
+            WORDS * 4
+        }
+
+        fn erase_row(&mut self, address: u32) -> Result<(), nvm::Error> {
+            self.spend()?;
+            let start = address as usize / 4;
+            for word in &mut self.words[start..start + ROW_SIZE / 4] {
+                *word = ERASED;
+            }
+            Ok(())
+        }
+
+        fn write(&mut self, address: u32, data: &[u32]) -> Result<(), nvm::Error> {
+            for (i, word) in data.iter().enumerate() {
+                self.spend()?;
+                // Programming can only clear bits
+                self.words[address as usize / 4 + i] &= *word;
+            }
+            Ok(())
+        }
+
+        fn read(&self, address: u32, data: &mut [u32]) -> Result<(), nvm::Error> {
+            let start = address as usize / 4;
+            data.copy_from_slice(&self.words[start..start + data.len()]);
+            Ok(())
+        }
+    }
+
+    /// Fill the active sector with updates of keys 1 to 3, leaving
+    /// their values at 10, 20 and 30
+    fn fill(flash: &mut FakeFlash) {
+        let mut eeprom = EmulatedEeprom::new(flash, 0, 1).unwrap();
+        let capacity = eeprom.capacity() as u32;
+        for i in 0..capacity - 3 {
+            eeprom.set(1 + (i % 3) as u16, 1000 + i).unwrap();
+        }
+        eeprom.set(1, 10).unwrap();
+        eeprom.set(2, 20).unwrap();
+        eeprom.set(3, 30).unwrap();
+        assert_eq!(eeprom.next, eeprom.sector_size);
+    }
+
+    #[test]
+    fn values_persist() {
+        let mut flash = FakeFlash::new();
+        {
+            let mut eeprom = EmulatedEeprom::new(&mut flash, 0, 1).unwrap();
+            assert_eq!(eeprom.get(7).unwrap(), None);
+            eeprom.set(7, 0xdead_beef).unwrap();
+            eeprom.set(8, 0).unwrap();
+            assert_eq!(eeprom.set(INVALID_KEY, 1), Err(Error::InvalidKey));
+        }
+        let eeprom = EmulatedEeprom::new(&mut flash, 0, 1).unwrap();
+        assert_eq!(eeprom.get(7).unwrap(), Some(0xdead_beef));
+        assert_eq!(eeprom.get(8).unwrap(), Some(0));
+    }
+
+    #[test]
+    fn swap_keeps_latest_values() {
+        let mut flash = FakeFlash::new();
+        fill(&mut flash);
+        {
+            let mut eeprom = EmulatedEeprom::new(&mut flash, 0, 1).unwrap();
+            eeprom.set(2, 21).unwrap();
+            assert_eq!(eeprom.active, 1);
+            assert_eq!(eeprom.get(1).unwrap(), Some(10));
+            assert_eq!(eeprom.get(2).unwrap(), Some(21));
+        }
+        let eeprom = EmulatedEeprom::new(&mut flash, 0, 1).unwrap();
+        assert_eq!(eeprom.active, 1);
+        assert_eq!(eeprom.get(1).unwrap(), Some(10));
+        assert_eq!(eeprom.get(2).unwrap(), Some(21));
+        assert_eq!(eeprom.get(3).unwrap(), Some(30));
+    }
+
+    #[test]
+    fn full_keeps_previous_values() {
+        let mut flash = FakeFlash::new();
+        let mut eeprom = EmulatedEeprom::new(&mut flash, 0, 1).unwrap();
+        let capacity = eeprom.capacity() as u16;
+        for key in 0..capacity {
+            eeprom.set(key, key as u32).unwrap();
+        }
+        assert_eq!(eeprom.set(capacity, 0), Err(Error::Full));
+        assert_eq!(eeprom.get(capacity).unwrap(), None);
+        // Updating a stored key still fits after the swap
+        eeprom.set(0, 100).unwrap();
+        for key in 1..capacity {
+            assert_eq!(eeprom.get(key).unwrap(), Some(key as u32));
+        }
+        assert_eq!(eeprom.get(0).unwrap(), Some(100));
+    }
+
+    #[test]
+    fn power_loss_during_swap_keeps_a_value() {
+        let mut budget = 0;
+        loop {
+            let mut flash = FakeFlash::new();
+            fill(&mut flash);
+            flash.budget = budget;
+            let done = match EmulatedEeprom::new(&mut flash, 0, 1) {
+                Ok(mut eeprom) => eeprom.set(2, 21).is_ok(),
+                Err(_) => false,
+            };
+
+            flash.budget = usize::MAX;
+            let eeprom = EmulatedEeprom::new(&mut flash, 0, 1).unwrap();
+            let value = eeprom.get(2).unwrap();
+            if done {
+                assert_eq!(value, Some(21));
+            } else {
+                assert!(value == Some(20) || value == Some(21), "budget {}: {:?}", budget, value);
+            }
+            assert_eq!(eeprom.get(1).unwrap(), Some(10), "budget {}", budget);
+            assert_eq!(eeprom.get(3).unwrap(), Some(30), "budget {}", budget);
+            if done {
+                break;
+            }
+            budget += 1;
+        }
+    }
+}
@@ -0,0 +1,348 @@
+//! Working with the External Interrupt Controller (EIC).
+//!
+//! The EIC detects edges or levels on the external interrupt lines and
+//! raises the `EIC` interrupt, or wakes the device from sleep.  Each line
+//! `EXTINTn` can be driven by one of a handful of IO pins; a pin is routed
+//! to its line by converting it into the matching `ExtIntN` type using the
+//! `into_ei` method of the `EicPin` trait.
+//!
+//! ```no_run
+//! let gclk1 = clocks.gclk1();
+//! let mut eic = Eic::init(
+//!     &mut peripherals.PM,
+//!     clocks.eic(&gclk1).unwrap(),
+//!     peripherals.EIC,
+//! );
+//! let mut button: ExtInt2<_> = pins.pa18.into_pull_up_input(&mut pins.port)
+//!     .into_ei(&mut pins.port);
+//! button.sense(&mut eic, Sense::Fall);
+//! button.enable_interrupt(&mut eic);
+//! ```
+use crate::clock::EicClock;
+use crate::gpio::{self, IntoFunction, Port};
+use crate::target_device::{EIC, PM};
+
+/// The signal condition that triggers an external interrupt
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Sense {
+    /// No detection
+    None = 0,
+    /// Rising edge detection
+    Rise = 1,
+    /// Falling edge detection
+    Fall = 2,
+    /// Detection on both edges
+    Both = 3,
+    /// High level detection
+    High = 4,
+    /// Low level detection
+    Low = 5,
+}
+
+/// `Eic` encapsulates the EIC hardware.  It is needed to change the
+/// configuration of the individual external interrupt lines.
+pub struct Eic {
+    eic: EIC,
+}
+
+impl Eic {
+    /// Power on, reset and enable the EIC.  The `EicClock` clocks the
+    /// edge detection and filtering logic; use a 32kHz clock generator
+    /// to detect interrupts while in standby.
+    pub fn init(pm: &mut PM, _clock: EicClock, eic: EIC) -> Self {
+        pm.apbamask.modify(|_, w| w.eic_().set_bit());
+
+        eic.ctrl.modify(|_, w| w.swrst().set_bit());
+        while eic.status.read().syncbusy().bit_is_set()
+            || eic.ctrl.read().swrst().bit_is_set()
+        {}
+
+        eic.ctrl.modify(|_, w| w.enable().set_bit());
+        while eic.status.read().syncbusy().bit_is_set() {}
+
+        Self { eic }
+    }
+
+    /// Disable the EIC and release the peripheral
+    pub fn free(self) -> EIC {
+        self.eic.ctrl.modify(|_, w| w.enable().clear_bit());
+        while self.eic.status.read().syncbusy().bit_is_set() {}
+        self.eic
+    }
+
+    fn set_sense(&mut self, line: u8, sense: Sense) {
+        let shift = (line as u32 % 8) * 4;
+        self.eic.config[line as usize / 8].modify(|r, w| unsafe {
+            w.bits((r.bits() & !(0x7 << shift)) | (sense as u32) << shift)
+        });
+    }
+
+    fn set_filter(&mut self, line: u8, filter: bool) {
+        let shift = (line as u32 % 8) * 4;
+        let value = if filter { 0x8 } else { 0 };
+        self.eic.config[line as usize / 8].modify(|r, w| unsafe {
+            w.bits((r.bits() & !(0x8 << shift)) | value << shift)
+        });
+    }
+
+    fn set_wakeup(&mut self, line: u8, enabled: bool) {
+        self.eic.wakeup.modify(|r, w| unsafe {
+            if enabled {
+                w.bits(r.bits() | 1 << line)
+            } else {
+                w.bits(r.bits() & !(1 << line))
+            }
+        });
+    }
+
+    fn set_interrupt(&mut self, line: u8, enabled: bool) {
+        if enabled {
+            self.eic.intenset.write(|w| unsafe { w.bits(1 << line) });
+        } else {
+            self.eic.intenclr.write(|w| unsafe { w.bits(1 << line) });
+        }
+    }
+
+    fn set_event_output(&mut self, line: u8, enabled: bool) {
+        self.eic.evctrl.modify(|r, w| unsafe {
+            if enabled {
+                w.bits(r.bits() | 1 << line)
+            } else {
+                w.bits(r.bits() & !(1 << line))
+            }
+        });
+    }
+}
+
+/// The EicPin trait makes it more ergonomic to convert a pin into an
+/// external interrupt line.  You should not implement this trait for
+/// yourself; only the implementations in the eic module make sense.
+pub trait EicPin<T> {
+    fn into_ei(self, port: &mut Port) -> T;
+}
+
+/// The ei macro defines the given external interrupt line type and
+/// implements EicPin for the given pins.  The EicPin implementation
+/// switches the pin to peripheral function A, preserving the pull up or
+/// pull down configuration of the pin.
+macro_rules! ei {
+    ($ExtInt:ident: $num:expr, {
+        $($(#[$attr:meta])* $PinType:ident,)+
+    }) => {
+/// Represents the external interrupt line with the matching number,
+/// driven by the contained pin.
+pub struct $ExtInt<GPIO> {
+    pin: GPIO,
+}
+
+impl<GPIO> $ExtInt<GPIO> {
+    /// The number of the external interrupt line
+    pub const LINE: u8 = $num;
+
+    /// Construct the interrupt line from the appropriate pin.  You may
+    /// find it more convenient to use the `into_ei` trait.
+    pub fn new(pin: GPIO) -> Self {
+        $ExtInt { pin }
+    }
+
+    /// Set the signal condition that triggers the interrupt
+    pub fn sense(&mut self, eic: &mut Eic, sense: Sense) {
+        eic.set_sense($num, sense);
+    }
+
+    /// Enable or disable the majority vote filter, which requires
+    /// three consecutive samples to agree before detecting a change.
+    pub fn filter(&mut self, eic: &mut Eic, enabled: bool) {
+        eic.set_filter($num, enabled);
+    }
+
+    /// Enable waking the device from sleep when this line triggers.
+    /// The interrupt itself must also be enabled.
+    pub fn enable_interrupt_wake(&mut self, eic: &mut Eic) {
+        eic.set_wakeup($num, true);
+    }
+
+    /// Disable waking the device from sleep when this line triggers.
+    pub fn disable_interrupt_wake(&mut self, eic: &mut Eic) {
+        eic.set_wakeup($num, false);
+    }
+
+    /// Enable generating an event when this line triggers
+    pub fn enable_event(&mut self, eic: &mut Eic) {
+        eic.set_event_output($num, true);
+    }
+
+    /// Disable generating an event when this line triggers
+    pub fn disable_event(&mut self, eic: &mut Eic) {
+        eic.set_event_output($num, false);
+    }
+
+    /// Enable the interrupt generation for this line.
+    /// This method only configures the EIC to raise the interrupt;
+    /// it does not configure the interrupt controller or define an
+    /// interrupt handler.
+    pub fn enable_interrupt(&mut self, eic: &mut Eic) {
+        eic.set_interrupt($num, true);
+    }
+
+    /// Disables interrupt generation for this line.
+    pub fn disable_interrupt(&mut self, eic: &mut Eic) {
+        eic.set_interrupt($num, false);
+    }
+
+    /// Returns true if this line has triggered since the flag was
+    /// last cleared
+    pub fn is_interrupt(&self) -> bool {
+        // Reading INTFLAG has no side effects
+        let eic = unsafe { &*EIC::ptr() };
+        eic.intflag.read().bits() & (1 << $num) != 0
+    }
+
+    /// Clear the interrupt flag of this line
+    pub fn clear_interrupt(&mut self) {
+        // Writing a 1 clears only the flag of this line
+        let eic = unsafe { &*EIC::ptr() };
+        eic.intflag.write(|w| unsafe { w.bits(1 << $num) });
+    }
+
+    /// Release the pin.  The line configuration is left untouched.
+    pub fn free(self) -> GPIO {
+        self.pin
+    }
+}
+
+$(
+    $(#[$attr])*
+    impl<MODE> EicPin<$ExtInt<gpio::$PinType<gpio::PfA>>> for gpio::$PinType<MODE> {
+        fn into_ei(self, port: &mut Port) -> $ExtInt<gpio::$PinType<gpio::PfA>> {
+            $ExtInt::new(self.into_function(port))
+        }
+    }
+
+)+
+    };
+}
+
+// The pins that can drive each external interrupt line
+// (Table 7-1 in the datasheet).  PA08 is only connected to the NMI.
+
+ei!(ExtInt0: 0, {
+    Pa0,
+    Pa16,
+    #[cfg(any(feature = "samd21g18a", feature="samd21j18a"))]
+    Pb0,
+    #[cfg(any(feature = "samd21g18a", feature="samd21j18a"))]
+    Pb16,
+});
+
+ei!(ExtInt1: 1, {
+    Pa1,
+    Pa17,
+    #[cfg(any(feature = "samd21g18a", feature="samd21j18a"))]
+    Pb1,
+    #[cfg(any(feature = "samd21g18a", feature="samd21j18a"))]
+    Pb17,
+});
+
+ei!(ExtInt2: 2, {
+    Pa2,
+    Pa18,
+    #[cfg(any(feature = "samd21g18a", feature="samd21j18a"))]
+    Pb2,
+});
+
+ei!(ExtInt3: 3, {
+    Pa3,
+    Pa19,
+    #[cfg(any(feature = "samd21g18a", feature="samd21j18a"))]
+    Pb3,
+});
+
+ei!(ExtInt4: 4, {
+    Pa4,
+    Pa20,
+    #[cfg(any(feature = "samd21g18a", feature="samd21j18a"))]
+    Pb4,
+});
+
+ei!(ExtInt5: 5, {
+    Pa5,
+    Pa21,
+    #[cfg(any(feature = "samd21g18a", feature="samd21j18a"))]
+    Pb5,
+});
+
+ei!(ExtInt6: 6, {
+    Pa6,
+    Pa22,
+    #[cfg(any(feature = "samd21g18a", feature="samd21j18a"))]
+    Pb6,
+    #[cfg(any(feature = "samd21g18a", feature="samd21j18a"))]
+    Pb22,
+});
+
+ei!(ExtInt7: 7, {
+    Pa7,
+    Pa23,
+    #[cfg(any(feature = "samd21g18a", feature="samd21j18a"))]
+    Pb7,
+    #[cfg(any(feature = "samd21g18a", feature="samd21j18a"))]
+    Pb23,
+});
+
+ei!(ExtInt8: 8, {
+    Pa28,
+    #[cfg(any(feature = "samd21g18a", feature="samd21j18a"))]
+    Pb8,
+});
+
+ei!(ExtInt9: 9, {
+    Pa9,
+    #[cfg(any(feature = "samd21g18a", feature="samd21j18a"))]
+    Pb9,
+});
+
+ei!(ExtInt10: 10, {
+    Pa10,
+    Pa30,
+    #[cfg(any(feature = "samd21g18a", feature="samd21j18a"))]
+    Pb10,
+});
+
+ei!(ExtInt11: 11, {
+    Pa11,
+    Pa31,
+    #[cfg(any(feature = "samd21g18a", feature="samd21j18a"))]
+    Pb11,
+});
+
+ei!(ExtInt12: 12, {
+    Pa12,
+    Pa24,
+    #[cfg(any(feature = "samd21g18a", feature="samd21j18a"))]
+    Pb12,
+});
+
+ei!(ExtInt13: 13, {
+    Pa13,
+    Pa25,
+    #[cfg(any(feature = "samd21g18a", feature="samd21j18a"))]
+    Pb13,
+});
+
+ei!(ExtInt14: 14, {
+    Pa14,
+    #[cfg(any(feature = "samd21g18a", feature="samd21j18a"))]
+    Pb14,
+    #[cfg(any(feature = "samd21g18a", feature="samd21j18a"))]
+    Pb30,
+});
+
+ei!(ExtInt15: 15, {
+    Pa15,
+    Pa27,
+    #[cfg(any(feature = "samd21g18a", feature="samd21j18a"))]
+    Pb15,
+    #[cfg(any(feature = "samd21g18a", feature="samd21j18a"))]
+    Pb31,
+});
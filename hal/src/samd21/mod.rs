@@ -1,11 +1,23 @@
 pub mod calibration;
+pub mod capture;
 pub mod clock;
+pub mod dac;
+pub mod device_id;
+pub mod dmac;
+pub mod eeprom;
+pub mod eic;
+pub mod monotonic;
+pub mod nvm;
 pub mod pwm;
+pub mod rtc;
 pub mod sercom;
 pub mod timer; 
 
 #[cfg(feature = "unproven")]
 pub mod adc;
 
+#[cfg(feature = "unproven")]
+pub mod watchdog;
+
 #[cfg(feature = "usb")]
 pub mod usb;
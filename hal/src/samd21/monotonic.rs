@@ -0,0 +1,274 @@
+//! Monotonic clocks for scheduling tasks with cortex-m-rtfm.
+//!
+//! The DWT cycle counter used by default by the rtfm scheduler stops
+//! while the core sleeps.  The clocks in this module are backed by
+//! peripherals that keep counting instead:
+//!
+//! * `TcMonotonic` pairs TC4 and TC5 into a free running 32-bit counter
+//!   that keeps running in standby.
+//! * `RtcMonotonic` uses the RTC in 32-bit counter mode.
+//!
+//! Both also provide a compare match interrupt for the application's
+//! own use.  The rtfm scheduler does not use it: its timer queue is
+//! still driven by SysTick, which stops in standby, so these clocks keep
+//! the time across sleep but do not wake the device for the next
+//! scheduled task.
+//!
+//! With the `rtfm` feature enabled, both implement
+//! `cortex_m_rtfm::Monotonic` and can be named as the `monotonic` of an
+//! rtfm application; `start` must be called from `init`, before any
+//! task is scheduled.
+//!
+//! ```no_run
+//! #[rtfm::app(device = hal::target_device, monotonic = hal::monotonic::TcMonotonic)]
+//! const APP: () = {
+//!     #[init(schedule = [blink])]
+//!     fn init(cx: init::Context) {
+//!         let mut clocks = GenericClockController::with_internal_32kosc(..);
+//!         let gclk0 = clocks.gclk0();
+//!         let tc45 = clocks.tc4_tc5(&gclk0).unwrap();
+//!         let tc4 = TimerCounter::tc4_(&tc45, cx.device.TC4, &mut cx.device.PM);
+//!         let tc5 = TimerCounter::tc5_(&tc45, cx.device.TC5, &mut cx.device.PM);
+//!         TcMonotonic::start(tc4, tc5, clocks.gclk0().into());
+//!         cx.schedule.blink(cx.start + TcMonotonic::duration(500.ms())).unwrap();
+//!     }
+//! };
+//! ```
+use crate::rtc::{Count32Mode, Rtc};
+use crate::target_device::rtc::RegisterBlock;
+use crate::target_device::tc3::COUNT32;
+use crate::target_device::{RTC, TC4};
+use crate::time::{Duration, Hertz, Instant, Milliseconds};
+use crate::timer::{TimerCounter4, TimerCounter5};
+use core::sync::atomic::{AtomicU32, Ordering};
+
+/// The frequency of the core clock, used to convert ticks into core
+/// cycles for the rtfm scheduler
+static CORE_FREQ: AtomicU32 = AtomicU32::new(0);
+static TC_FREQ: AtomicU32 = AtomicU32::new(0);
+static RTC_FREQ: AtomicU32 = AtomicU32::new(0);
+
+fn duration(tick_freq: u32, ms: Milliseconds) -> Duration {
+    Duration::from_ticks((ms.0 as u64 * tick_freq as u64 / 1_000) as u32)
+}
+
+/// A monotonic clock backed by TC4 and TC5 paired into a 32-bit counter
+pub struct TcMonotonic;
+
+impl TcMonotonic {
+    /// Pair TC4 and TC5 into a 32-bit counter and start it counting at
+    /// the frequency of the `Tc4Tc5Clock`.  TC4 is the master of the pair
+    /// and holds the counter and compare registers; TC5 is unusable while
+    /// paired.  `core_freq` is the frequency of the core clock.
+    pub fn start(master: TimerCounter4, _slave: TimerCounter5, core_freq: Hertz) {
+        TC_FREQ.store(master.freq.0, Ordering::Relaxed);
+        CORE_FREQ.store(core_freq.0, Ordering::Relaxed);
+
+        let count = master.tc.count32();
+        count.ctrla.write(|w| w.swrst().set_bit());
+        while count.status.read().syncbusy().bit_is_set() {}
+        // the SVD erroneously marks swrst as write-only, so we
+        // need to manually read the bit here
+        while count.ctrla.read().bits() & 1 != 0 {}
+
+        // Continuously synchronize COUNT so that it can be read at any time
+        count.readreq.write(|w| unsafe {
+            w.rcont().set_bit();
+            w.addr().bits(0x10)
+        });
+        count.ctrla.write(|w| {
+            w.mode().count32();
+            w.prescaler().div1();
+            // Keep counting while the device sleeps
+            w.runstdby().set_bit();
+            w.enable().set_bit()
+        });
+        while count.status.read().syncbusy().bit_is_set() {}
+    }
+
+    fn count32() -> &'static COUNT32 {
+        // TC4 is owned by this clock after `start`
+        unsafe { (*TC4::ptr()).count32() }
+    }
+
+    /// Returns the current time
+    pub fn now() -> Instant {
+        Instant::from_ticks(Self::count32().count.read().bits())
+    }
+
+    /// Returns the frequency of the counter
+    pub fn tick_freq() -> Hertz {
+        Hertz(TC_FREQ.load(Ordering::Relaxed))
+    }
+
+    /// Converts `ms` into a duration of counter ticks
+    pub fn duration<T: Into<Milliseconds>>(ms: T) -> Duration {
+        duration(TC_FREQ.load(Ordering::Relaxed), ms.into())
+    }
+
+    /// Set the instant at which the compare match interrupt triggers
+    pub fn set_compare(instant: Instant) {
+        let count = Self::count32();
+        count.cc[0].write(|w| unsafe { w.cc().bits(instant.ticks()) });
+        while count.status.read().syncbusy().bit_is_set() {}
+    }
+
+    /// Enable the compare match interrupt.
+    /// This method only configures the timer to raise the `TC4`
+    /// interrupt; it does not configure the interrupt controller or
+    /// define an interrupt handler.
+    pub fn enable_compare_interrupt() {
+        Self::count32().intenset.write(|w| w.mc0().set_bit());
+    }
+
+    /// Disables the compare match interrupt
+    pub fn disable_compare_interrupt() {
+        Self::count32().intenclr.write(|w| w.mc0().set_bit());
+    }
+
+    /// Returns true if the compare match has occurred since the flag
+    /// was last cleared
+    pub fn is_compare_interrupt() -> bool {
+        Self::count32().intflag.read().mc0().bit_is_set()
+    }
+
+    /// Clear the compare match flag
+    pub fn clear_compare_interrupt() {
+        // Writing a 1 clears the flag
+        Self::count32().intflag.write(|w| w.mc0().set_bit());
+    }
+}
+
+/// A monotonic clock backed by the RTC in 32-bit counter mode
+pub struct RtcMonotonic;
+
+impl RtcMonotonic {
+    /// Start the clock from an RTC in 32-bit counter mode.  The counter
+    /// is reset and runs freely at the frequency of the `RtcClock`.
+    /// `core_freq` is the frequency of the core clock.
+    pub fn start(mut rtc: Rtc<Count32Mode>, core_freq: Hertz) {
+        RTC_FREQ.store(rtc.rtc_clock_freq().0, Ordering::Relaxed);
+        CORE_FREQ.store(core_freq.0, Ordering::Relaxed);
+
+        rtc.set_compare(0, false);
+        rtc.set_count32(0);
+        // The RTC stays enabled when the handle is dropped
+        rtc.clear_compare_interrupt();
+    }
+
+    fn rtc() -> &'static RegisterBlock {
+        // The RTC is owned by this clock after `start`
+        unsafe { &*RTC::ptr() }
+    }
+
+    fn sync() {
+        while Self::rtc().mode0().status.read().syncbusy().bit_is_set() {}
+    }
+
+    /// Returns the current time
+    pub fn now() -> Instant {
+        let mode0 = Self::rtc().mode0();
+        mode0.readreq.write(|w| w.rreq().set_bit());
+        Self::sync();
+        Instant::from_ticks(mode0.count.read().bits())
+    }
+
+    /// Returns the frequency of the counter
+    pub fn tick_freq() -> Hertz {
+        Hertz(RTC_FREQ.load(Ordering::Relaxed))
+    }
+
+    /// Converts `ms` into a duration of counter ticks
+    pub fn duration<T: Into<Milliseconds>>(ms: T) -> Duration {
+        duration(RTC_FREQ.load(Ordering::Relaxed), ms.into())
+    }
+
+    /// Set the instant at which the compare match interrupt triggers
+    pub fn set_compare(instant: Instant) {
+        Self::rtc().mode0().comp[0].write(|w| unsafe { w.bits(instant.ticks()) });
+        Self::sync();
+    }
+
+    /// Enable the compare match interrupt.
+    /// This method only configures the RTC to raise the interrupt;
+    /// it does not configure the interrupt controller or define an
+    /// interrupt handler.
+    pub fn enable_compare_interrupt() {
+        Self::rtc().mode0().intenset.write(|w| w.cmp0().set_bit());
+    }
+
+    /// Disables the compare match interrupt
+    pub fn disable_compare_interrupt() {
+        Self::rtc().mode0().intenclr.write(|w| w.cmp0().set_bit());
+    }
+
+    /// Returns true if the compare match has occurred since the flag
+    /// was last cleared
+    pub fn is_compare_interrupt() -> bool {
+        Self::rtc().mode0().intflag.read().cmp0().bit_is_set()
+    }
+
+    /// Clear the compare match flag
+    pub fn clear_compare_interrupt() {
+        // Writing a 1 clears the flag
+        Self::rtc().mode0().intflag.write(|w| w.cmp0().set_bit());
+    }
+}
+
+#[cfg(feature = "rtfm")]
+mod rtfm {
+    use super::{RtcMonotonic, TcMonotonic, CORE_FREQ, RTC_FREQ, TC_FREQ};
+    use crate::time::Instant;
+    use core::sync::atomic::Ordering;
+    use cortex_m_rtfm::{Fraction, Monotonic};
+
+    fn ratio(tick_freq: u32) -> Fraction {
+        Fraction {
+            numerator: CORE_FREQ.load(Ordering::Relaxed),
+            denominator: tick_freq,
+        }
+    }
+
+    impl Monotonic for TcMonotonic {
+        type Instant = Instant;
+
+        fn ratio() -> Fraction {
+            ratio(TC_FREQ.load(Ordering::Relaxed))
+        }
+
+        fn now() -> Instant {
+            TcMonotonic::now()
+        }
+
+        unsafe fn reset() {
+            let count = TcMonotonic::count32();
+            count.count.write(|w| w.count().bits(0));
+            while count.status.read().syncbusy().bit_is_set() {}
+        }
+
+        fn zero() -> Instant {
+            Instant::from_ticks(0)
+        }
+    }
+
+    impl Monotonic for RtcMonotonic {
+        type Instant = Instant;
+
+        fn ratio() -> Fraction {
+            ratio(RTC_FREQ.load(Ordering::Relaxed))
+        }
+
+        fn now() -> Instant {
+            RtcMonotonic::now()
+        }
+
+        unsafe fn reset() {
+            RtcMonotonic::rtc().mode0().count.write(|w| w.bits(0));
+            RtcMonotonic::sync();
+        }
+
+        fn zero() -> Instant {
+            Instant::from_ticks(0)
+        }
+    }
+}
@@ -0,0 +1,220 @@
+//! Working with the Non-Volatile Memory Controller (NVMCTRL).
+//!
+//! The flash is organized in pages, which are the unit of writing, and
+//! rows of four pages, which are the unit of erasing.  Erasing sets every
+//! bit of the row; writing can only clear bits, so a row must be erased
+//! before its pages are rewritten.  The flash is also divided into 16
+//! lock regions that protect it against accidental erasing and writing.
+//!
+//! The CPU stalls on flash accesses while the NVM controller is busy, so
+//! the methods of `Nvm` block until each operation has completed.
+//!
+//! ```no_run
+//! let mut nvm = Nvm::new(peripherals.NVMCTRL);
+//! let address = (nvm.flash_size() - nvm.row_size()) as u32;
+//! nvm.erase_row(address).unwrap();
+//! nvm.write(address, &[0xdead_beef, 0x1234_5678]).unwrap();
+//! ```
+use crate::target_device::nvmctrl::ctrla;
+use crate::target_device::NVMCTRL;
+use core::ptr;
+
+/// The number of lock regions of the flash
+pub const LOCK_REGIONS: u8 = 16;
+
+/// The number of pages in a row
+pub const PAGES_PER_ROW: usize = 4;
+
+/// Errors reported by the NVM controller
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The address is outside the flash, or not aligned to the unit of
+    /// the operation
+    InvalidAddress,
+    /// The operation targets a locked region
+    Locked,
+    /// An invalid command or command key was used
+    Programming,
+    /// The NVM controller reported an error
+    NvmError,
+}
+
+/// `Nvm` encapsulates the NVMCTRL hardware and provides erasing, writing
+/// and locking of the flash.
+pub struct Nvm {
+    nvm: NVMCTRL,
+}
+
+impl Nvm {
+    /// Take ownership of the NVMCTRL.  The flash is switched to manual
+    /// write mode, so that pages are only written by an explicit command.
+    pub fn new(nvm: NVMCTRL) -> Self {
+        nvm.ctrlb.modify(|_, w| w.manw().set_bit());
+        Self { nvm }
+    }
+
+    /// Release the NVMCTRL
+    pub fn free(self) -> NVMCTRL {
+        self.nvm
+    }
+
+    /// Returns the size of a page in bytes
+    pub fn page_size(&self) -> usize {
+        8 << self.nvm.param.read().psz().bits()
+    }
+
+    /// Returns the number of pages in the flash
+    pub fn page_count(&self) -> usize {
+        self.nvm.param.read().nvmp().bits() as usize
+    }
+
+    /// Returns the size of a row in bytes
+    pub fn row_size(&self) -> usize {
+        self.page_size() * PAGES_PER_ROW
+    }
+
+    /// Returns the size of the flash in bytes
+    pub fn flash_size(&self) -> usize {
+        self.page_size() * self.page_count()
+    }
+
+    /// Returns the size of a lock region in bytes
+    pub fn lock_region_size(&self) -> usize {
+        self.flash_size() / LOCK_REGIONS as usize
+    }
+
+    /// Returns the lock region containing `address`
+    pub fn region_of(&self, address: u32) -> u8 {
+        (address as usize / self.lock_region_size()) as u8
+    }
+
+    /// Returns true if the lock region `region` is locked
+    pub fn is_region_locked(&self, region: u8) -> bool {
+        // A cleared bit indicates a locked region
+        self.nvm.lock.read().lock().bits() & (1 << region) == 0
+    }
+
+    /// Lock the lock region `region` until it is unlocked or the device
+    /// is reset.  The lock bits in the user row set the state at reset.
+    pub fn lock_region(&mut self, region: u8) -> Result<(), Error> {
+        let address = self.region_address(region)?;
+        self.command(address, |w| w.cmd().lr())
+    }
+
+    /// Unlock the lock region `region` until it is locked or the device
+    /// is reset
+    pub fn unlock_region(&mut self, region: u8) -> Result<(), Error> {
+        let address = self.region_address(region)?;
+        self.command(address, |w| w.cmd().ur())
+    }
+
+    /// Erase the row starting at `address`, which must be aligned to the
+    /// row size
+    pub fn erase_row(&mut self, address: u32) -> Result<(), Error> {
+        let row_size = self.row_size();
+        self.check_range(address, row_size, row_size)?;
+        self.command(address, |w| w.cmd().er())?;
+        self.invalidate_cache()
+    }
+
+    /// Write `data` starting at `address`, which must be word aligned.
+    /// The data may span several pages, which must have been erased.
+    pub fn write(&mut self, address: u32, data: &[u32]) -> Result<(), Error> {
+        self.check_range(address, data.len() * 4, 4)?;
+
+        let page_size = self.page_size() as u32;
+        let mut address = address;
+        let mut data = data;
+        while !data.is_empty() {
+            let page = address & !(page_size - 1);
+            let words = data.len().min(((page + page_size - address) / 4) as usize);
+
+            self.command(page, |w| w.cmd().pbc())?;
+            for (i, word) in data[..words].iter().enumerate() {
+                // The page buffer is written through the flash address space
+                unsafe { ptr::write_volatile((address as *mut u32).add(i), *word) };
+            }
+            self.command(page, |w| w.cmd().wp())?;
+
+            address += words as u32 * 4;
+            data = &data[words..];
+        }
+        self.invalidate_cache()
+    }
+
+    /// Read `data.len()` words starting at `address`, which must be word
+    /// aligned
+    pub fn read(&self, address: u32, data: &mut [u32]) -> Result<(), Error> {
+        self.check_range(address, data.len() * 4, 4)?;
+        for (i, word) in data.iter_mut().enumerate() {
+            *word = unsafe { ptr::read_volatile((address as *const u32).add(i)) };
+        }
+        Ok(())
+    }
+
+    fn region_address(&self, region: u8) -> Result<u32, Error> {
+        if region >= LOCK_REGIONS {
+            return Err(Error::InvalidAddress);
+        }
+        Ok((region as usize * self.lock_region_size()) as u32)
+    }
+
+    /// Check that `len` bytes from `address` are within the flash and
+    /// unlocked, and that `address` is aligned to `align` bytes
+    fn check_range(&self, address: u32, len: usize, align: usize) -> Result<(), Error> {
+        let in_flash = match (address as usize).checked_add(len) {
+            Some(end) => end <= self.flash_size(),
+            None => false,
+        };
+        if address as usize % align != 0 || !in_flash {
+            return Err(Error::InvalidAddress);
+        }
+        if len == 0 {
+            return Ok(());
+        }
+        let first = self.region_of(address);
+        let last = self.region_of(address + len as u32 - 1);
+        if (first..=last).any(|region| self.is_region_locked(region)) {
+            return Err(Error::Locked);
+        }
+        Ok(())
+    }
+
+    fn invalidate_cache(&mut self) -> Result<(), Error> {
+        self.command(0, |w| w.cmd().invall())
+    }
+
+    /// Execute a command on `address` and wait for it to complete
+    fn command<F>(&mut self, address: u32, cmd: F) -> Result<(), Error>
+    where
+        F: FnOnce(&mut ctrla::W) -> &mut ctrla::W,
+    {
+        while self.nvm.intflag.read().ready().bit_is_clear() {}
+
+        // Clear the errors of any previous command
+        self.nvm.status.write(|w| {
+            w.proge().set_bit();
+            w.locke().set_bit();
+            w.nvme().set_bit()
+        });
+        // ADDR holds a 16-bit word address
+        self.nvm.addr.write(|w| unsafe { w.addr().bits(address / 2) });
+        self.nvm.ctrla.write(|w| {
+            cmd(w);
+            w.cmdex().key()
+        });
+
+        while self.nvm.intflag.read().ready().bit_is_clear() {}
+
+        let status = self.nvm.status.read();
+        if status.locke().bit_is_set() {
+            Err(Error::Locked)
+        } else if status.proge().bit_is_set() {
+            Err(Error::Programming)
+        } else if status.nvme().bit_is_set() {
+            Err(Error::NvmError)
+        } else {
+            Ok(())
+        }
+    }
+}
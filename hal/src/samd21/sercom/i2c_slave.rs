@@ -0,0 +1,320 @@
+use crate::clock;
+use crate::sercom::I2CError;
+use crate::target_device::sercom0::I2CS;
+use crate::target_device::{PM, SERCOM0, SERCOM1, SERCOM2, SERCOM3};
+#[cfg(any(feature = "samd21g18a", feature="samd21j18a"))]
+use crate::target_device::{SERCOM4, SERCOM5};
+use nb;
+
+/// Release the bus and wait for the next start condition
+const SLAVE_ACT_WAIT: u8 = 2;
+/// Acknowledge and carry on with the transfer
+const SLAVE_ACT_RESPOND: u8 = 3;
+
+/// The addresses an I2C slave responds to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum I2CSlaveAddress {
+    /// Respond to a single address
+    Single(u16),
+    /// Respond to `address`, ignoring the bits that are set in `mask`
+    Masked { address: u16, mask: u16 },
+    /// Respond to either of two addresses
+    Dual(u16, u16),
+}
+
+/// An event on the bus that requires the attention of an I2C slave,
+/// returned by `poll`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum I2CSlaveEvent {
+    /// The master addressed this slave, to read from it if `read` is set
+    /// or to write to it otherwise.  The address has been acknowledged.
+    AddressMatch { read: bool },
+    /// The master wrote a byte, which has been acknowledged
+    Received(u8),
+    /// The master waits for a byte, to be given with `respond`
+    TransmitRequest,
+    /// The master ended the transaction with a stop condition
+    Stop,
+}
+
+/// The I2C slave interrupts that can be enabled
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum I2CSlaveInterrupt {
+    /// The master addressed this slave
+    AddressMatch,
+    /// A byte was received, or the master waits for one
+    DataReady,
+    /// The master sent a stop condition
+    Stop,
+    /// A bus error, collision or timeout occurred
+    Error,
+}
+
+/// Define an I2C slave type for the given SERCOM and pad pair.
+macro_rules! i2c_slave {
+    ([
+        $($Type:ident: ($pad0:ident, $pad1:ident, $SERCOM:ident, $powermask:ident, $clock:ident),)+
+    ]) => {
+        $(
+/// Represents the Sercom instance configured to act as an I2C Slave.
+/// Bus activity is reported as `I2CSlaveEvent`s by `poll`, which can be
+/// called from the SERCOM interrupt handler once the interrupts are
+/// enabled.
+pub struct $Type<$pad0, $pad1> {
+    sda: $pad0,
+    scl: $pad1,
+    sercom: $SERCOM,
+}
+
+impl<$pad0, $pad1> $Type<$pad0, $pad1> {
+    /// Configures the sercom instance to work as an I2C Slave responding
+    /// to the 7-bit `address`.
+    /// The clock is obtained via the `GenericClockGenerator` type; the bus
+    /// clock is driven by the master.
+    ///
+    /// ```no_run
+    /// let mut i2c = I2CSlave3::new(
+    ///     &clocks.sercom3_core(&gclk0).unwrap(),
+    ///     p.device.SERCOM3,
+    ///     &mut p.device.PM,
+    ///     pins.pa22.into_pad(&mut pins.port),
+    ///     pins.pa23.into_pad(&mut pins.port),
+    ///     I2CSlaveAddress::Single(0x42),
+    /// );
+    /// ```
+    pub fn new(
+        clock: &clock::$clock,
+        sercom: $SERCOM,
+        pm: &mut PM,
+        sda: $pad0,
+        scl: $pad1,
+        address: I2CSlaveAddress,
+    ) -> Self {
+        Self::configure(clock, sercom, pm, sda, scl, address, false)
+    }
+
+    /// Configures the sercom instance like `new`, responding to the
+    /// 10-bit `address`
+    pub fn new_10bit(
+        clock: &clock::$clock,
+        sercom: $SERCOM,
+        pm: &mut PM,
+        sda: $pad0,
+        scl: $pad1,
+        address: I2CSlaveAddress,
+    ) -> Self {
+        Self::configure(clock, sercom, pm, sda, scl, address, true)
+    }
+
+    fn configure(
+        _clock: &clock::$clock,
+        sercom: $SERCOM,
+        pm: &mut PM,
+        sda: $pad0,
+        scl: $pad1,
+        address: I2CSlaveAddress,
+        ten_bit: bool,
+    ) -> Self {
+        // Power up the peripheral bus clock.
+        // safe because we're exclusively owning SERCOM
+        pm.apbcmask.modify(|_, w| w.$powermask().set_bit());
+
+        let (amode, addr, addrmask) = match address {
+            I2CSlaveAddress::Single(address) => (0, address, 0),
+            I2CSlaveAddress::Masked { address, mask } => (0, address, mask),
+            I2CSlaveAddress::Dual(first, second) => (1, first, second),
+        };
+
+        unsafe {
+            // reset the sercom instance
+            sercom.i2cs().ctrla.modify(|_, w| w.swrst().set_bit());
+            // wait for reset to complete
+            while sercom.i2cs().syncbusy.read().swrst().bit_is_set()
+                || sercom.i2cs().ctrla.read().swrst().bit_is_set()
+            {}
+
+            // Put the hardware into i2c slave mode
+            sercom.i2cs().ctrla.modify(|_, w| w.mode().i2c_slave());
+            // wait for configuration to take effect
+            while sercom.i2cs().syncbusy.read().enable().bit_is_set() {}
+
+            sercom.i2cs().addr.write(|w| {
+                w.addr().bits(addr);
+                w.addrmask().bits(addrmask);
+                w.tenbiten().bit(ten_bit)
+            });
+
+            // Smart mode acknowledges received bytes when DATA is read
+            sercom.i2cs().ctrlb.modify(|_, w| {
+                w.smen().set_bit();
+                w.amode().bits(amode)
+            });
+
+            sercom.i2cs().ctrla.modify(|_, w| w.enable().set_bit());
+            // wait for configuration to take effect
+            while sercom.i2cs().syncbusy.read().enable().bit_is_set() {}
+        }
+
+        Self { sda, scl, sercom }
+    }
+
+    /// Returns the next event on the bus, acknowledging addresses and
+    /// received bytes.  Errors are reported once and cleared.
+    pub fn poll(&mut self) -> nb::Result<I2CSlaveEvent, I2CError> {
+        let intflag = self.i2cs().intflag.read();
+        let status = self.i2cs().status.read();
+
+        if intflag.error().bit_is_set() {
+            self.i2cs().status.write(|w| {
+                w.buserr().set_bit();
+                w.coll().set_bit();
+                w.lowtout().set_bit();
+                w.sexttout().set_bit()
+            });
+            self.i2cs().intflag.write(|w| w.error().set_bit());
+            return Err(nb::Error::Other(if status.buserr().bit_is_set() {
+                I2CError::BusError
+            } else if status.coll().bit_is_set() {
+                I2CError::ArbitrationLost
+            } else {
+                I2CError::Timeout
+            }));
+        }
+
+        if intflag.amatch().bit_is_set() {
+            // Writing the command clears the flag
+            self.cmd(SLAVE_ACT_RESPOND);
+            return Ok(I2CSlaveEvent::AddressMatch {
+                read: status.dir().bit_is_set(),
+            });
+        }
+
+        if intflag.drdy().bit_is_set() {
+            if status.dir().bit_is_clear() {
+                // Reading DATA acknowledges the byte and clears the flag
+                return Ok(I2CSlaveEvent::Received(self.i2cs().data.read().data().bits()));
+            }
+            if status.rxnack().bit_is_set() {
+                // The master is done reading; the stop follows
+                self.cmd(SLAVE_ACT_WAIT);
+                return Err(nb::Error::WouldBlock);
+            }
+            return Ok(I2CSlaveEvent::TransmitRequest);
+        }
+
+        if intflag.prec().bit_is_set() {
+            self.i2cs().intflag.write(|w| w.prec().set_bit());
+            return Ok(I2CSlaveEvent::Stop);
+        }
+
+        Err(nb::Error::WouldBlock)
+    }
+
+    /// Send `byte` in answer to `I2CSlaveEvent::TransmitRequest`
+    pub fn respond(&mut self, byte: u8) {
+        unsafe {
+            self.i2cs().data.write(|w| w.data().bits(byte));
+        }
+    }
+
+    /// Enable the interrupt generation for `interrupt`.
+    /// This method only configures the SERCOM to raise the interrupt;
+    /// it does not configure the interrupt controller or define an
+    /// interrupt handler.
+    pub fn enable_interrupt(&mut self, interrupt: I2CSlaveInterrupt) {
+        self.i2cs().intenset.write(|w| match interrupt {
+            I2CSlaveInterrupt::AddressMatch => w.amatch().set_bit(),
+            I2CSlaveInterrupt::DataReady => w.drdy().set_bit(),
+            I2CSlaveInterrupt::Stop => w.prec().set_bit(),
+            I2CSlaveInterrupt::Error => w.error().set_bit(),
+        });
+    }
+
+    /// Disable the interrupt generation for `interrupt`
+    pub fn disable_interrupt(&mut self, interrupt: I2CSlaveInterrupt) {
+        self.i2cs().intenclr.write(|w| match interrupt {
+            I2CSlaveInterrupt::AddressMatch => w.amatch().set_bit(),
+            I2CSlaveInterrupt::DataReady => w.drdy().set_bit(),
+            I2CSlaveInterrupt::Stop => w.prec().set_bit(),
+            I2CSlaveInterrupt::Error => w.error().set_bit(),
+        });
+    }
+
+    /// Breaks the sercom device up into its constituent pins and the SERCOM
+    /// instance.  Does not make any changes to power management.
+    pub fn free(self) -> ($pad0, $pad1, $SERCOM) {
+        (self.sda, self.scl, self.sercom)
+    }
+
+    fn cmd(&mut self, cmd: u8) {
+        unsafe {
+            self.i2cs().ctrlb.modify(|_, w| {
+                // clear bit means send ack
+                w.ackact().clear_bit();
+                w.cmd().bits(cmd)
+            });
+        }
+    }
+
+    fn i2cs(&mut self) -> &I2CS {
+        &self.sercom.i2cs()
+    }
+}
+        )+
+    };
+}
+
+i2c_slave!([
+    I2CSlave0:
+        (
+            Sercom0Pad0,
+            Sercom0Pad1,
+            SERCOM0,
+            sercom0_,
+            Sercom0CoreClock
+        ),
+    I2CSlave1:
+        (
+            Sercom1Pad0,
+            Sercom1Pad1,
+            SERCOM1,
+            sercom1_,
+            Sercom1CoreClock
+        ),
+    I2CSlave2:
+        (
+            Sercom2Pad0,
+            Sercom2Pad1,
+            SERCOM2,
+            sercom2_,
+            Sercom2CoreClock
+        ),
+    I2CSlave3:
+        (
+            Sercom3Pad0,
+            Sercom3Pad1,
+            SERCOM3,
+            sercom3_,
+            Sercom3CoreClock
+        ),
+]);
+
+#[cfg(any(feature = "samd21g18a", feature="samd21j18a"))]
+i2c_slave!([
+    I2CSlave4:
+        (
+            Sercom4Pad0,
+            Sercom4Pad1,
+            SERCOM4,
+            sercom4_,
+            Sercom4CoreClock
+        ),
+    I2CSlave5:
+        (
+            Sercom5Pad0,
+            Sercom5Pad1,
+            SERCOM5,
+            sercom5_,
+            Sercom5CoreClock
+        ),
+]);
@@ -2,6 +2,7 @@ use crate::clock;
 use crate::time::Hertz;
 use crate::hal::spi::{FullDuplex, Mode, Phase, Polarity};
 use nb;
+use crate::dmac::{self, BeatSize, Channel, ChannelId, Priority, TriggerAction, TriggerSource};
 use crate::sercom::pads::*;
 use crate::target_device::sercom0::SPI;
 use crate::target_device::{PM, SERCOM0, SERCOM1, SERCOM2, SERCOM3};
@@ -11,6 +12,54 @@ use crate::target_device::{SERCOM4, SERCOM5};
 #[derive(Debug)]
 pub enum Error {
     Overrun,
+    /// The DMA channel servicing a transfer reported an error
+    Dma(dmac::Error),
+    /// A DMA transfer buffer is longer than 65535 bytes
+    BufferLength,
+}
+
+/// An SPI write driven by the DMAC, returned by `SPIMasterX::dma_write`.
+///
+/// Poll the transfer with `poll` or block on it with `wait`, then use
+/// `free` to get back the SPI instance, the DMA channel and the buffer.
+pub struct SpiDmaWrite<SPI, TX> {
+    spi: SPI,
+    tx: Channel<TX>,
+    buffer: &'static [u8],
+    dma_done: bool,
+}
+
+/// A full-duplex SPI transfer driven by the DMAC, returned by
+/// `SPIMasterX::dma_transfer`.  The received bytes replace the contents of
+/// the buffer as it is sent.
+///
+/// Poll the transfer with `poll` or block on it with `wait`, then use
+/// `free` to get back the SPI instance, the DMA channels and the buffer.
+pub struct SpiDmaTransfer<SPI, TX, RX> {
+    spi: SPI,
+    tx: Channel<TX>,
+    rx: Channel<RX>,
+    buffer: &'static mut [u8],
+    tx_done: bool,
+    rx_done: bool,
+}
+
+fn dma_poll<CH: ChannelId>(channel: &mut Channel<CH>) -> nb::Result<(), Error> {
+    match channel.wait() {
+        Ok(()) => Ok(()),
+        Err(nb::Error::WouldBlock) => Err(nb::Error::WouldBlock),
+        Err(nb::Error::Other(e)) => Err(nb::Error::Other(Error::Dma(e))),
+    }
+}
+
+/// The number of DMA beats needed for `buffer`, which a single
+/// descriptor limits to 65535
+fn dma_len(buffer: &[u8]) -> Result<u16, Error> {
+    if buffer.len() > u16::MAX as usize {
+        Err(Error::BufferLength)
+    } else {
+        Ok(buffer.len() as u16)
+    }
 }
 
 /// The DipoDopo trait defines a way to get the data in and data out pin out
@@ -217,6 +266,163 @@ macro_rules! spi_master {
         #[cfg(feature = "unproven")]
         impl<MISO, MOSI, SCK> ::hal::blocking::spi::write_iter::Default<u8> for $Type<MISO, MOSI, SCK> {}
 
+
+        $crate::paste::item! {
+            impl<MISO, MOSI, SCK> $Type<MISO, MOSI, SCK> {
+                /// Write `buffer` out using the `tx` DMA channel, without
+                /// involving the CPU.  Any data received meanwhile is
+                /// discarded.  The returned handle owns the SPI instance
+                /// until the transfer is freed.
+                ///
+                /// An empty buffer completes immediately, while a buffer
+                /// longer than 65535 bytes is not sent and makes `poll`
+                /// return `Error::BufferLength`.
+                pub fn dma_write<TX: ChannelId>(
+                    mut self,
+                    mut tx: Channel<TX>,
+                    buffer: &'static [u8],
+                ) -> SpiDmaWrite<Self, TX> {
+                    if let Ok(len @ 1..=0xffff) = dma_len(buffer) {
+                        let data = self.data_address();
+
+                        tx.configure(TriggerSource::[<$SERCOM _TX>], TriggerAction::Beat, Priority::Level0);
+                        tx.descriptor()
+                            .configure(BeatSize::Byte, len, buffer.as_ptr() as u32, true, data, false)
+                            .link(None);
+                        tx.start();
+                    }
+
+                    SpiDmaWrite {
+                        spi: self,
+                        tx,
+                        buffer,
+                        dma_done: false,
+                    }
+                }
+
+                /// Send `buffer` using the `tx` DMA channel while storing the
+                /// received bytes back into `buffer` using the `rx` DMA
+                /// channel.  The returned handle owns the SPI instance
+                /// until the transfer is freed.
+                ///
+                /// Buffer lengths are handled as for `dma_write`.
+                pub fn dma_transfer<TX: ChannelId, RX: ChannelId>(
+                    mut self,
+                    mut tx: Channel<TX>,
+                    mut rx: Channel<RX>,
+                    buffer: &'static mut [u8],
+                ) -> SpiDmaTransfer<Self, TX, RX> {
+                    if let Ok(len @ 1..=0xffff) = dma_len(buffer) {
+                        let data = self.data_address();
+                        self.clear_rx();
+
+                        // The receive channel has the higher priority so that
+                        // it always drains DATA before the next byte arrives
+                        rx.configure(TriggerSource::[<$SERCOM _RX>], TriggerAction::Beat, Priority::Level1);
+                        rx.descriptor()
+                            .configure(BeatSize::Byte, len, data, false, buffer.as_mut_ptr() as u32, true)
+                            .link(None);
+                        tx.configure(TriggerSource::[<$SERCOM _TX>], TriggerAction::Beat, Priority::Level0);
+                        tx.descriptor()
+                            .configure(BeatSize::Byte, len, buffer.as_ptr() as u32, true, data, false)
+                            .link(None);
+                        rx.start();
+                        tx.start();
+                    }
+
+                    SpiDmaTransfer {
+                        spi: self,
+                        tx,
+                        rx,
+                        buffer,
+                        tx_done: false,
+                        rx_done: false,
+                    }
+                }
+
+                /// Address of the DATA register, used as the DMA source
+                /// and destination
+                fn data_address(&mut self) -> u32 {
+                    &self.spi().data as *const _ as u32
+                }
+
+                /// Clear any receive overflow and discard pending data
+                fn clear_rx(&mut self) {
+                    self.spi().status.write(|w| w.bufovf().set_bit());
+                    while self.spi().intflag.read().rxc().bit_is_set() {
+                        self.spi().data.read();
+                    }
+                }
+            }
+
+            impl<MISO, MOSI, SCK, TX: ChannelId> SpiDmaWrite<$Type<MISO, MOSI, SCK>, TX> {
+                /// Returns `Ok` once all bytes have been shifted out
+                pub fn poll(&mut self) -> nb::Result<(), Error> {
+                    if dma_len(self.buffer)? == 0 {
+                        return Ok(());
+                    }
+                    if !self.dma_done {
+                        dma_poll(&mut self.tx)?;
+                        self.dma_done = true;
+                    }
+                    // The DMAC is done once the last byte is written to
+                    // DATA; wait for it to leave the shift register too
+                    if self.spi.spi().intflag.read().txc().bit_is_set() {
+                        Ok(())
+                    } else {
+                        Err(nb::Error::WouldBlock)
+                    }
+                }
+
+                /// Block until the transfer completes
+                pub fn wait(&mut self) -> Result<(), Error> {
+                    nb::block!(self.poll())
+                }
+
+                /// Stop the transfer if it is still running, and release
+                /// the SPI instance, the DMA channel and the buffer
+                pub fn free(mut self) -> ($Type<MISO, MOSI, SCK>, Channel<TX>, &'static [u8]) {
+                    self.tx.stop();
+                    self.spi.clear_rx();
+                    (self.spi, self.tx, self.buffer)
+                }
+            }
+
+            impl<MISO, MOSI, SCK, TX: ChannelId, RX: ChannelId> SpiDmaTransfer<$Type<MISO, MOSI, SCK>, TX, RX> {
+                /// Returns `Ok` once all bytes have been exchanged
+                pub fn poll(&mut self) -> nb::Result<(), Error> {
+                    if dma_len(self.buffer)? == 0 {
+                        return Ok(());
+                    }
+                    // `Channel::wait` consumes the completion flag, so
+                    // latch each channel once it is done
+                    if !self.tx_done {
+                        dma_poll(&mut self.tx)?;
+                        self.tx_done = true;
+                    }
+                    // Every byte sent is matched by a byte received, so the
+                    // transfer is done once the receive channel completes
+                    if !self.rx_done {
+                        dma_poll(&mut self.rx)?;
+                        self.rx_done = true;
+                    }
+                    Ok(())
+                }
+
+                /// Block until the transfer completes
+                pub fn wait(&mut self) -> Result<(), Error> {
+                    nb::block!(self.poll())
+                }
+
+                /// Stop the transfer if it is still running, and release
+                /// the SPI instance, the DMA channels and the buffer
+                pub fn free(mut self) -> ($Type<MISO, MOSI, SCK>, Channel<TX>, Channel<RX>, &'static mut [u8]) {
+                    self.tx.stop();
+                    self.rx.stop();
+                    (self.spi, self.tx, self.rx, self.buffer)
+                }
+            }
+        }
     };
 
 }
@@ -18,6 +18,657 @@ pub trait RxpoTxpo {
     fn rxpo_txpo(&self) -> (u8, u8);
 }
 
+/// The parity bit added to each character
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Parity {
+    None,
+    Even,
+    Odd,
+}
+
+/// The number of stop bits ending each character
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StopBits {
+    One,
+    Two,
+}
+
+/// The number of data bits in each character
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CharSize {
+    Five,
+    Six,
+    Seven,
+    Eight,
+    /// Use `read_word` and `write_word` to access the ninth bit
+    Nine,
+}
+
+/// The order in which the data bits are transmitted
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BitOrder {
+    LsbFirst,
+    MsbFirst,
+}
+
+/// The oversampling of the receiver and the way the BAUD register
+/// divides the clock.  Fractional generation splits the division into
+/// eighths, which reaches baud rates that arithmetic generation
+/// approximates poorly.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SampleRate {
+    /// The highest oversampling whose baud rate is within 1% of the
+    /// requested one, or else whichever setting comes closest
+    Auto,
+    /// 16x oversampling with arithmetic baud generation
+    Arithmetic16,
+    /// 16x oversampling with fractional baud generation
+    Fractional16,
+    /// 8x oversampling with arithmetic baud generation
+    Arithmetic8,
+    /// 8x oversampling with fractional baud generation
+    Fractional8,
+    /// 3x oversampling with arithmetic baud generation
+    Arithmetic3,
+}
+
+impl SampleRate {
+    /// Returns the value of the SAMPR field
+    fn sampr(self) -> u8 {
+        match self {
+            SampleRate::Auto => unreachable!(),
+            SampleRate::Arithmetic16 => 0,
+            SampleRate::Fractional16 => 1,
+            SampleRate::Arithmetic8 => 2,
+            SampleRate::Fractional8 => 3,
+            SampleRate::Arithmetic3 => 4,
+        }
+    }
+
+    /// Returns the number of samples per bit
+    fn samples(self) -> u8 {
+        match self {
+            SampleRate::Auto => unreachable!(),
+            SampleRate::Arithmetic16 | SampleRate::Fractional16 => 16,
+            SampleRate::Arithmetic8 | SampleRate::Fractional8 => 8,
+            SampleRate::Arithmetic3 => 3,
+        }
+    }
+
+    fn is_fractional(self) -> bool {
+        self == SampleRate::Fractional16 || self == SampleRate::Fractional8
+    }
+
+    /// Returns the raw value of the BAUD register that comes closest to
+    /// `baud` from a clock of `fref`, and the baud rate it produces, or
+    /// `None` if the baud rate is out of reach
+    fn baud_setting(self, fref: u32, baud: u32) -> Option<(u16, u32)> {
+        let samples = self.samples() as u64;
+        let baud = baud.max(1) as u64;
+        let fref = fref as u64;
+        if samples * baud > fref {
+            return None;
+        }
+
+        if self.is_fractional() {
+            // BAUD + FP / 8 = fref / (samples * baud), rounded to the
+            // nearest eighth
+            let eighths = (fref * 8 + samples * baud / 2) / (samples * baud);
+            if !(8..=0x1fff * 8 + 7).contains(&eighths) {
+                return None;
+            }
+            let value = (eighths / 8) as u16 | (((eighths % 8) as u16) << 13);
+            Some((value, (fref * 8 / (samples * eighths)) as u32))
+        } else {
+            // Asynchronous arithmetic mode (Table 24-2 in datasheet)
+            let value = calculate_baud_value(baud as u32, fref as u32, samples as u8);
+            let actual = fref * (65536 - value as u64) / (samples * 65536);
+            Some((value, actual as u32))
+        }
+    }
+
+    /// Resolve `Auto` into the sample rate to use for `baud`, returning
+    /// it along with its BAUD register value and actual baud rate, or
+    /// `ConfigError::BaudRate` if the baud rate is out of reach
+    fn resolve(self, fref: u32, baud: u32) -> Result<(SampleRate, u16, u32), ConfigError> {
+        if self != SampleRate::Auto {
+            return match self.baud_setting(fref, baud) {
+                Some((value, actual)) => Ok((self, value, actual)),
+                None => Err(ConfigError::BaudRate),
+            };
+        }
+
+        // Higher oversampling is more tolerant of noise and clock
+        // mismatch, so take the first oversampling that gets within 1%
+        // of the baud rate.  Failing that, keep the closest setting seen.
+        let candidates: [&[SampleRate]; 3] = [
+            &[SampleRate::Arithmetic16, SampleRate::Fractional16],
+            &[SampleRate::Arithmetic8, SampleRate::Fractional8],
+            &[SampleRate::Arithmetic3],
+        ];
+        let error = |actual: u32| (actual as i64 - baud as i64).abs();
+        let mut best: Option<(SampleRate, u16, u32)> = None;
+        for rates in candidates.iter() {
+            for &rate in rates.iter() {
+                if let Some((value, actual)) = rate.baud_setting(fref, baud) {
+                    let closer = match best {
+                        Some((_, _, best_actual)) => error(actual) < error(best_actual),
+                        None => true,
+                    };
+                    if closer {
+                        best = Some((rate, value, actual));
+                    }
+                }
+            }
+            if let Some((_, _, actual)) = best {
+                if error(actual) * 100 <= baud as i64 {
+                    break;
+                }
+            }
+        }
+        best.ok_or(ConfigError::BaudRate)
+    }
+}
+
+/// The framing and sampling of a UART, built up from the 8N1 default:
+///
+/// ```no_run
+/// // 8E1, as required by Modbus RTU
+/// let config = UartConfig::default().parity(Parity::Even);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UartConfig {
+    pub parity: Parity,
+    pub stop_bits: StopBits,
+    pub char_size: CharSize,
+    pub sample_rate: SampleRate,
+    pub bit_order: BitOrder,
+}
+
+impl core::default::Default for UartConfig {
+    /// 8 data bits, no parity, one stop bit, LSB first, with automatic
+    /// sampling
+    fn default() -> Self {
+        UartConfig {
+            parity: Parity::None,
+            stop_bits: StopBits::One,
+            char_size: CharSize::Eight,
+            sample_rate: SampleRate::Auto,
+            bit_order: BitOrder::LsbFirst,
+        }
+    }
+}
+
+impl UartConfig {
+    /// Set the parity
+    pub fn parity(mut self, parity: Parity) -> Self {
+        self.parity = parity;
+        self
+    }
+
+    /// Set the number of stop bits
+    pub fn stop_bits(mut self, stop_bits: StopBits) -> Self {
+        self.stop_bits = stop_bits;
+        self
+    }
+
+    /// Set the number of data bits
+    pub fn char_size(mut self, char_size: CharSize) -> Self {
+        self.char_size = char_size;
+        self
+    }
+
+    /// Set the sample rate and baud generation
+    pub fn sample_rate(mut self, sample_rate: SampleRate) -> Self {
+        self.sample_rate = sample_rate;
+        self
+    }
+
+    /// Set the bit order
+    pub fn bit_order(mut self, bit_order: BitOrder) -> Self {
+        self.bit_order = bit_order;
+        self
+    }
+}
+
+/// Errors reported by the UART in its STATUS register
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error {
+    /// A received character did not end with a stop bit.  The
+    /// character is discarded.
+    Framing,
+    /// A received character failed the parity check.  The character is
+    /// discarded.
+    Parity,
+    /// A character was lost because the receive buffer was full
+    Overflow,
+    /// The sync field following a break was inconsistent
+    InconsistentSync,
+    /// The line did not match the transmitted data, with collision
+    /// detection enabled
+    Collision,
+}
+
+/// Errors configuring the UART
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConfigError {
+    /// The baud rate can not be generated from the clock with the
+    /// requested sample rate
+    BaudRate,
+}
+
+/// Returns and clears the first receive error flagged in STATUS, along
+/// with the ERROR interrupt flag.  Framing and parity errors also discard
+/// the offending character.
+fn rx_error(usart: &USART) -> Option<Error> {
+    let status = usart.status.read();
+    // PERR, FERR, BUFOVF and ISF; bit 3 is the CTS line level
+    if status.bits() & 0x17 != 0 {
+        usart.intflag.write(|w| w.error().set_bit());
+    }
+    if status.bufovf().bit_is_set() {
+        usart.status.write(|w| w.bufovf().set_bit());
+        Some(Error::Overflow)
+    } else if status.ferr().bit_is_set() || status.perr().bit_is_set() {
+        usart.status.write(|w| {
+            w.ferr().set_bit();
+            w.perr().set_bit()
+        });
+        usart.data.read();
+        if status.ferr().bit_is_set() {
+            Some(Error::Framing)
+        } else {
+            Some(Error::Parity)
+        }
+    } else if status.isf().bit_is_set() {
+        usart.status.write(|w| w.isf().set_bit());
+        Some(Error::InconsistentSync)
+    } else {
+        None
+    }
+}
+
+/// Returns and clears a collision flagged in STATUS, along with the
+/// ERROR interrupt flag
+fn tx_error(usart: &USART) -> Option<Error> {
+    if usart.status.read().coll().bit_is_set() {
+        usart.status.write(|w| w.coll().set_bit());
+        usart.intflag.write(|w| w.error().set_bit());
+        Some(Error::Collision)
+    } else {
+        None
+    }
+}
+
+/// The UART interrupts that can be enabled
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UartInterrupt {
+    /// A received character can be read
+    ReceiveComplete,
+    /// The data register can take a character to transmit
+    DataRegisterEmpty,
+    /// The last character has been shifted out
+    TransmitComplete,
+    /// A receive error was flagged in STATUS
+    Error,
+}
+
+fn enable_interrupt(usart: &USART, interrupt: UartInterrupt) {
+    usart.intenset.write(|w| match interrupt {
+        UartInterrupt::ReceiveComplete => w.rxc().set_bit(),
+        UartInterrupt::DataRegisterEmpty => w.dre().set_bit(),
+        UartInterrupt::TransmitComplete => w.txc().set_bit(),
+        UartInterrupt::Error => w.error().set_bit(),
+    });
+}
+
+fn disable_interrupt(usart: &USART, interrupt: UartInterrupt) {
+    usart.intenclr.write(|w| match interrupt {
+        UartInterrupt::ReceiveComplete => w.rxc().set_bit(),
+        UartInterrupt::DataRegisterEmpty => w.dre().set_bit(),
+        UartInterrupt::TransmitComplete => w.txc().set_bit(),
+        UartInterrupt::Error => w.error().set_bit(),
+    });
+}
+
+/// Read a received character, reporting receive errors
+fn read_word(usart: &USART) -> nb::Result<u16, Error> {
+    if usart.intflag.read().rxc().bit_is_clear() {
+        return Err(nb::Error::WouldBlock);
+    }
+    if let Some(err) = rx_error(usart) {
+        return Err(nb::Error::Other(err));
+    }
+
+    Ok(usart.data.read().bits())
+}
+
+/// Returns false while hardware flow control is enabled and the CTS
+/// line is deasserted by the other end
+fn clear_to_send(usart: &USART) -> bool {
+    // TXPO 2 is the only pin out with RTS and CTS; STATUS.CTS reflects
+    // the level of the active low CTS pad
+    usart.ctrla.read().txpo().bits() != 2 || usart.status.read().cts().bit_is_clear()
+}
+
+/// Write a character once the data register is empty, reporting
+/// collisions.  With flow control the hardware holds the character back
+/// until the other end asserts CTS.
+fn write_word(usart: &USART, word: u16) -> nb::Result<(), Error> {
+    if let Some(err) = tx_error(usart) {
+        return Err(nb::Error::Other(err));
+    }
+    if usart.intflag.read().dre().bit_is_clear() {
+        return Err(nb::Error::WouldBlock);
+    }
+
+    unsafe {
+        usart.data.write(|w| w.bits(word));
+    }
+
+    Ok(())
+}
+
+fn flush(usart: &USART) -> nb::Result<(), Error> {
+    if let Some(err) = tx_error(usart) {
+        return Err(nb::Error::Other(err));
+    }
+
+    // simply await DRE empty
+    if usart.intflag.read().dre().bit_is_clear() {
+        return Err(nb::Error::WouldBlock);
+    }
+
+    Ok(())
+}
+
+/// The interrupt control of the UART halves, used by the buffered
+/// drivers.  You should not implement this trait for yourself; only the
+/// implementations in the sercom module make sense.
+pub trait UartHalf {
+    /// Enable the interrupt generation for `interrupt`.
+    /// This method only configures the SERCOM to raise the interrupt;
+    /// it does not configure the interrupt controller or define an
+    /// interrupt handler.
+    fn enable_interrupt(&mut self, interrupt: UartInterrupt);
+
+    /// Disable the interrupt generation for `interrupt`
+    fn disable_interrupt(&mut self, interrupt: UartInterrupt);
+}
+
+/// A queue of bytes over caller provided storage, which holds one byte
+/// less than the length of the storage
+pub struct RingBuffer {
+    storage: &'static mut [u8],
+    read: usize,
+    write: usize,
+}
+
+impl RingBuffer {
+    pub fn new(storage: &'static mut [u8]) -> Self {
+        assert!(storage.len() > 1, "the ring buffer storage is too small");
+        RingBuffer {
+            storage,
+            read: 0,
+            write: 0,
+        }
+    }
+
+    fn next(&self, index: usize) -> usize {
+        (index + 1) % self.storage.len()
+    }
+
+    /// Returns the number of queued bytes
+    pub fn len(&self) -> usize {
+        (self.write + self.storage.len() - self.read) % self.storage.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.read == self.write
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.next(self.write) == self.read
+    }
+
+    /// Append `byte`, or return it if the queue is full
+    pub fn push(&mut self, byte: u8) -> Result<(), u8> {
+        if self.is_full() {
+            return Err(byte);
+        }
+        self.storage[self.write] = byte;
+        self.write = self.next(self.write);
+        Ok(())
+    }
+
+    /// Returns the oldest byte without removing it
+    pub fn peek(&self) -> Option<u8> {
+        if self.is_empty() {
+            None
+        } else {
+            Some(self.storage[self.read])
+        }
+    }
+
+    /// Remove and return the oldest byte
+    pub fn pop(&mut self) -> Option<u8> {
+        let byte = self.peek()?;
+        self.read = self.next(self.read);
+        Some(byte)
+    }
+
+    /// Release the storage
+    pub fn free(self) -> &'static mut [u8] {
+        self.storage
+    }
+}
+
+/// A receiver that buffers characters from the SERCOM interrupt.
+///
+/// Call `on_interrupt` from the interrupt handler of the SERCOM, and read
+/// the buffered characters from anywhere else.
+pub struct BufferedRx<RX> {
+    rx: RX,
+    buffer: RingBuffer,
+    error: Option<Error>,
+}
+
+impl<RX> BufferedRx<RX>
+where
+    RX: serial::Read<u8, Error = Error> + UartHalf,
+{
+    /// Buffer the characters received by `rx` in `storage`, and enable
+    /// the receive complete interrupt
+    pub fn new(mut rx: RX, storage: &'static mut [u8]) -> Self {
+        rx.enable_interrupt(UartInterrupt::ReceiveComplete);
+        BufferedRx {
+            rx,
+            buffer: RingBuffer::new(storage),
+            error: None,
+        }
+    }
+
+    /// Move the received characters into the buffer.  A character that
+    /// does not fit is dropped and reported as an `Overflow`.
+    pub fn on_interrupt(&mut self) {
+        loop {
+            match self.rx.read() {
+                Ok(byte) => {
+                    if self.buffer.push(byte).is_err() {
+                        self.error = Some(Error::Overflow);
+                    }
+                }
+                Err(nb::Error::Other(err)) => self.error = Some(err),
+                Err(nb::Error::WouldBlock) => break,
+            }
+        }
+    }
+
+    /// Returns the number of buffered characters
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    /// Disable the interrupt and release the receiver and the storage
+    pub fn free(mut self) -> (RX, &'static mut [u8]) {
+        self.rx.disable_interrupt(UartInterrupt::ReceiveComplete);
+        (self.rx, self.buffer.free())
+    }
+}
+
+impl<RX> serial::Read<u8> for BufferedRx<RX>
+where
+    RX: serial::Read<u8, Error = Error> + UartHalf,
+{
+    type Error = Error;
+
+    /// Take the oldest buffered character.  An error recorded by
+    /// `on_interrupt` is reported once, before the remaining characters.
+    fn read(&mut self) -> nb::Result<u8, Self::Error> {
+        if let Some(err) = self.error.take() {
+            return Err(nb::Error::Other(err));
+        }
+        self.buffer.pop().ok_or(nb::Error::WouldBlock)
+    }
+}
+
+/// A transmitter that queues characters for the SERCOM interrupt.
+///
+/// Call `on_interrupt` from the interrupt handler of the SERCOM; the data
+/// register empty interrupt is enabled while characters are queued.
+pub struct BufferedTx<TX> {
+    tx: TX,
+    buffer: RingBuffer,
+    error: Option<Error>,
+}
+
+impl<TX> BufferedTx<TX>
+where
+    TX: serial::Write<u8, Error = Error> + UartHalf,
+{
+    /// Queue the characters to transmit with `tx` in `storage`
+    pub fn new(tx: TX, storage: &'static mut [u8]) -> Self {
+        BufferedTx {
+            tx,
+            buffer: RingBuffer::new(storage),
+            error: None,
+        }
+    }
+
+    /// Move queued characters into the data register while it is
+    /// empty, and disable the interrupt once the queue is drained
+    pub fn on_interrupt(&mut self) {
+        while let Some(byte) = self.buffer.peek() {
+            match self.tx.write(byte) {
+                Ok(()) => {
+                    self.buffer.pop();
+                }
+                Err(nb::Error::Other(err)) => self.error = Some(err),
+                Err(nb::Error::WouldBlock) => return,
+            }
+        }
+        self.tx.disable_interrupt(UartInterrupt::DataRegisterEmpty);
+    }
+
+    /// Returns the number of queued characters
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    /// Disable the interrupt and release the transmitter and the
+    /// storage.  Queued characters are dropped.
+    pub fn free(mut self) -> (TX, &'static mut [u8]) {
+        self.tx.disable_interrupt(UartInterrupt::DataRegisterEmpty);
+        (self.tx, self.buffer.free())
+    }
+}
+
+impl<TX> serial::Write<u8> for BufferedTx<TX>
+where
+    TX: serial::Write<u8, Error = Error> + UartHalf,
+{
+    type Error = Error;
+
+    /// Queue `word`, or block while the queue is full.  An error
+    /// recorded by `on_interrupt` is reported once.
+    fn write(&mut self, word: u8) -> nb::Result<(), Self::Error> {
+        if let Some(err) = self.error.take() {
+            return Err(nb::Error::Other(err));
+        }
+        self.buffer.push(word).map_err(|_| nb::Error::WouldBlock)?;
+        self.tx.enable_interrupt(UartInterrupt::DataRegisterEmpty);
+        Ok(())
+    }
+
+    /// Block until the queue is drained into the data register
+    fn flush(&mut self) -> nb::Result<(), Self::Error> {
+        if !self.buffer.is_empty() {
+            return Err(nb::Error::WouldBlock);
+        }
+        self.tx.flush()
+    }
+}
+
+impl<TX> Default<u8> for BufferedTx<TX> where
+    TX: serial::Write<u8, Error = Error> + UartHalf
+{
+}
+
+impl<TX> fmt::Write for BufferedTx<TX>
+where
+    TX: serial::Write<u8, Error = Error> + UartHalf,
+{
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.bwrite_all(s.as_bytes()).map_err(|_| fmt::Error)
+    }
+}
+
+/// Apply `config` to a disabled USART, along with the sample rate and
+/// BAUD register value resolved for it
+fn configure(usart: &USART, config: &UartConfig, sample_rate: SampleRate, value: u16) {
+    // Lots of union fields which require unsafe access
+    unsafe {
+        usart.ctrla.modify(|_, w| {
+            match config.bit_order {
+                BitOrder::LsbFirst => w.dord().set_bit(),
+                BitOrder::MsbFirst => w.dord().clear_bit(),
+            };
+            w.sampr().bits(sample_rate.sampr());
+            match config.parity {
+                Parity::None => w.form().bits(0), // USART frame
+                _ => w.form().bits(1),            // USART frame with parity
+            }
+        });
+
+        // The fractional layout of BAUD is included in `value`
+        usart.baud().write(|w| w.baud().bits(value));
+
+        usart.ctrlb.modify(|_, w| {
+            w.sbmode().bit(config.stop_bits == StopBits::Two);
+            w.chsize().bits(match config.char_size {
+                CharSize::Eight => 0,
+                CharSize::Nine => 1,
+                CharSize::Five => 5,
+                CharSize::Six => 6,
+                CharSize::Seven => 7,
+            });
+            w.pmode().bit(config.parity == Parity::Odd);
+            w.txen().set_bit();
+            w.rxen().set_bit()
+        });
+
+        while usart.syncbusy.read().ctrlb().bit_is_set() {}
+    }
+}
+
 /// Define a UARTX type for the given Sercom.
 ///
 /// Also defines the valid "pad to uart function" mappings for this instance so
@@ -40,8 +691,9 @@ macro_rules! uart {
         }
 
         /// Define a From instance for either a tuple of two SercomXPadX
-        /// instances, or a tuple of four SercomXPadX instances that converts
-        /// them into an UARTXPadout instance.
+        /// instances, a tuple of three with a CTS pad, or a tuple of four
+        /// SercomXPadX instances that converts them into an UARTXPadout
+        /// instance.
         ///
         /// Also defines a RxpoTxpo instance for the constructed padout instance
         /// that returns the values used to configure the sercom pads for the
@@ -63,6 +715,22 @@ macro_rules! uart {
                     }
                 }
             };
+            ($rxpo_txpo:expr => $pad0:ident, $pad1:ident, (), $pad3:ident) => {
+                $crate::paste::item! {
+                    /// Convert from a tuple of (RX, TX, CTS) to UARTXPadout
+                    impl<PIN0, PIN1, PIN3> From<([<$Sercom $pad0>]<PIN0>, [<$Sercom $pad1>]<PIN1>, [<$Sercom $pad3>]<PIN3>)> for [<$Type Padout>]<[<$Sercom $pad0>]<PIN0>, [<$Sercom $pad1>]<PIN1>, (), [<$Sercom $pad3>]<PIN3>> {
+                        fn from(pads: ([<$Sercom $pad0>]<PIN0>, [<$Sercom $pad1>]<PIN1>, [<$Sercom $pad3>]<PIN3>)) -> [<$Type Padout>]<[<$Sercom $pad0>]<PIN0>, [<$Sercom $pad1>]<PIN1>, (), [<$Sercom $pad3>]<PIN3>> {
+                            [<$Type Padout>] { _rx: pads.0, _tx: pads.1, _rts: (), _cts: pads.2 }
+                        }
+                    }
+
+                    impl<PIN0, PIN1, PIN3> RxpoTxpo for [<$Type Padout>]<[<$Sercom $pad0>]<PIN0>, [<$Sercom $pad1>]<PIN1>, (), [<$Sercom $pad3>]<PIN3>> {
+                        fn rxpo_txpo(&self) -> (u8, u8) {
+                            $rxpo_txpo
+                        }
+                    }
+                }
+            };
             ($rxpo_txpo:expr => $pad0:ident, $pad1:ident, $pad2:ident, $pad3:ident) => {
                 $crate::paste::item! {
                     /// Convert from a tuple of (RX, TX, RTS, CTS) to UARTXPadout
@@ -84,7 +752,11 @@ macro_rules! uart {
         padout!((0, 1) => Pad0, Pad2);
 
         padout!((1, 0) => Pad1, Pad0);
+        // TXPO 2 enables the RTS/CTS handshake; RTS is asserted while
+        // the receiver can take a character, and transmission waits for
+        // CTS.  RTS may be left unconnected.
         padout!((1, 2) => Pad1, Pad0, Pad2, Pad3);
+        padout!((1, 2) => Pad1, Pad0, (), Pad3);
         padout!((1, 1) => Pad1, Pad2);
 
         padout!((2, 0) => Pad2, Pad0);
@@ -103,6 +775,9 @@ macro_rules! uart {
             pub struct $Type<RX, TX, RTS, CTS> {
                 padout: [<$Type Padout>]<RX, TX, RTS, CTS>,
                 sercom: $SERCOM,
+                clock_freq: Hertz,
+                baud: Hertz,
+                actual_baud: Hertz,
             }
 
             impl<RX, TX, RTS, CTS> $Type<RX, TX, RTS, CTS> {
@@ -111,18 +786,53 @@ macro_rules! uart {
                 /// which pins are bound to the RX, TX and optionally RTS and CTS
                 /// functions.
                 ///
-                /// You can use any tuple of two or four SercomXPadY instances
-                /// for which there exists a From implementation for
-                /// UARTXPadout.
+                /// You can use any tuple of two, three or four SercomXPadY
+                /// instances for which there exists a From implementation for
+                /// UARTXPadout.  Mappings with a CTS pad enable hardware
+                /// flow control.
+                ///
+                /// The UART uses the 8N1 framing of `UartConfig::default()`;
+                /// see `with_config` for other framings.
+                ///
+                /// # Panics
+                ///
+                /// Panics if the baud rate can not be generated from the
+                /// clock; use `with_config` to handle that case.
                 pub fn new<F: Into<Hertz>, T: Into<[<$Type Padout>]<RX, TX, RTS, CTS>>>(
                     clock: &clock::$clock,
                     freq: F,
                     sercom: $SERCOM,
                     pm: &mut PM,
-                    padout: T
-                ) -> $Type<RX, TX, RTS, CTS> where
+                    padout: T,
+                ) -> Self where
+                    [<$Type Padout>]<RX, TX, RTS, CTS>: RxpoTxpo {
+                    match Self::with_config(clock, freq, sercom, pm, padout, UartConfig::default()) {
+                        Ok(uart) => uart,
+                        Err(_) => panic!("baud rate out of range for the clock"),
+                    }
+                }
+
+                /// Power on and configure SERCOMX like `new`, with the
+                /// framing and sampling given by `config`.  If the baud
+                /// rate can not be generated from the clock, returns
+                /// `ConfigError::BaudRate` along with the untouched SERCOM
+                /// and the padout.
+                pub fn with_config<F: Into<Hertz>, T: Into<[<$Type Padout>]<RX, TX, RTS, CTS>>>(
+                    clock: &clock::$clock,
+                    freq: F,
+                    sercom: $SERCOM,
+                    pm: &mut PM,
+                    padout: T,
+                    config: UartConfig,
+                ) -> Result<Self, (ConfigError, $SERCOM, [<$Type Padout>]<RX, TX, RTS, CTS>)> where
                     [<$Type Padout>]<RX, TX, RTS, CTS>: RxpoTxpo {
+                    let baud = freq.into();
                     let padout = padout.into();
+                    let (sample_rate, value, actual_baud) =
+                        match config.sample_rate.resolve(clock.freq().0, baud.0) {
+                            Ok(setting) => setting,
+                            Err(err) => return Err((err, sercom, padout)),
+                        };
 
                     pm.apbcmask.modify(|_, w| w.$powermask().set_bit());
 
@@ -137,60 +847,102 @@ macro_rules! uart {
 
                         // Unsafe b/c of direct call to bits on rxpo/txpo
                         sercom.usart().ctrla.modify(|_, w| {
-                            w.dord().set_bit();
-
                             let (rxpo, txpo) = padout.rxpo_txpo();
                             w.rxpo().bits(rxpo);
                             w.txpo().bits(txpo);
 
-                            w.form().bits(0x00);
-                            w.sampr().bits(0x00); // 16x oversample fractional
                             w.runstdby().set_bit(); // Run in standby
-                            w.form().bits(0); // 0 is no parity bits
-
                             w.mode().usart_int_clk() // Internal clock mode
                         });
+                    }
 
-                        // Calculate value for BAUD register
-                        let sample_rate: u8 = 16;
-                        let fref = clock.freq().0;
-
-            //          TODO: Support fractional BAUD mode
-            //            let mul_ratio = (fref.0 * 1000) / (freq.into().0 * 16);
-            //
-            //            let baud = mul_ratio / 1000;
-            //            let fp = ((mul_ratio - (baud*1000))*8)/1000;
-            //
-            //            sercom.usart().baud()_frac_mode.modify(|_, w| {
-            //                w.baud().bits(baud as u16);
-            //                w.fp().bits(fp as u8)
-            //            });
-
-                        // Asynchronous arithmetic mode (Table 24-2 in datasheet)
-                        let baud = calculate_baud_value(freq.into().0, fref, sample_rate);
-
-                        sercom.usart().baud().modify(|_, w| {
-                            w.baud().bits(baud)
-                        });
-
-                        sercom.usart().ctrlb.modify(|_, w| {
-                            w.sbmode().clear_bit(); // 0 is one stop bit see sec 25.8.2
-                            w.chsize().bits(0x0);
-                            w.txen().set_bit();
-                            w.rxen().set_bit()
-                        });
-
-                        while sercom.usart().syncbusy.read().ctrlb().bit_is_set() {}
+                    configure(sercom.usart(), &config, sample_rate, value);
 
-                        sercom.usart().ctrla.modify(|_, w| w.enable().set_bit());
-                        // wait for sync of ENABLE
-                        while sercom.usart().syncbusy.read().enable().bit_is_set() {}
-                    }
+                    sercom.usart().ctrla.modify(|_, w| w.enable().set_bit());
+                    // wait for sync of ENABLE
+                    while sercom.usart().syncbusy.read().enable().bit_is_set() {}
 
-                    Self {
+                    Ok(Self {
                         padout,
                         sercom,
-                    }
+                        clock_freq: clock.freq(),
+                        baud,
+                        actual_baud: Hertz(actual_baud),
+                    })
+                }
+
+                /// Change the baud rate, framing and sampling at runtime.
+                /// Any character being transferred is lost.  Returns
+                /// `ConfigError::BaudRate`, leaving the UART running as
+                /// before, if the baud rate can not be generated from the
+                /// clock.
+                pub fn reconfigure<F: Into<Hertz>>(&mut self, freq: F, config: UartConfig) -> Result<(), ConfigError> {
+                    let baud = freq.into();
+                    let (sample_rate, value, actual_baud) =
+                        config.sample_rate.resolve(self.clock_freq.0, baud.0)?;
+
+                    self.usart().ctrla.modify(|_, w| w.enable().clear_bit());
+                    while self.usart().syncbusy.read().enable().bit_is_set() {}
+
+                    configure(self.usart(), &config, sample_rate, value);
+                    self.baud = baud;
+                    self.actual_baud = Hertz(actual_baud);
+
+                    self.usart().ctrla.modify(|_, w| w.enable().set_bit());
+                    while self.usart().syncbusy.read().enable().bit_is_set() {}
+                    Ok(())
+                }
+
+                /// Returns the baud rate actually produced from the clock,
+                /// which approximates the requested one
+                pub fn baud_rate(&self) -> Hertz {
+                    self.actual_baud
+                }
+
+                /// Returns the deviation of the actual baud rate from the
+                /// requested one, in percent
+                pub fn baud_error(&self) -> f32 {
+                    (self.actual_baud.0 as f32 - self.baud.0 as f32) * 100.0 / self.baud.0.max(1) as f32
+                }
+
+                /// Write a character of up to 9 bits, as used with
+                /// `CharSize::Nine`
+                pub fn write_word(&mut self, word: u16) -> nb::Result<(), Error> {
+                    write_word(self.usart(), word)
+                }
+
+                /// Returns false while hardware flow control is enabled
+                /// and the other end deasserts CTS, which pauses the
+                /// transmission
+                pub fn clear_to_send(&self) -> bool {
+                    clear_to_send(self.usart())
+                }
+
+                /// Read a character of up to 9 bits, as used with
+                /// `CharSize::Nine`
+                pub fn read_word(&mut self) -> nb::Result<u16, Error> {
+                    read_word(self.usart())
+                }
+
+                /// Enable the interrupt generation for `interrupt`.
+                /// This method only configures the SERCOM to raise the
+                /// interrupt; it does not configure the interrupt controller
+                /// or define an interrupt handler.
+                pub fn enable_interrupt(&mut self, interrupt: UartInterrupt) {
+                    enable_interrupt(self.usart(), interrupt);
+                }
+
+                /// Disable the interrupt generation for `interrupt`
+                pub fn disable_interrupt(&mut self, interrupt: UartInterrupt) {
+                    disable_interrupt(self.usart(), interrupt);
+                }
+
+                /// Split the UART into a receiving and a transmitting half,
+                /// which can be owned by different tasks.  The SERCOM keeps
+                /// its configuration and the pads stay bound to it; the
+                /// halves can not be joined back.
+                pub fn split(self) -> ([<$Type Rx>], [<$Type Tx>]) {
+                    ([<$Type Rx>] { _private: () }, [<$Type Tx>] { _private: () })
                 }
 
                 pub fn free(self) -> ([<$Type Padout>]<RX, TX, RTS, CTS>, $SERCOM) {
@@ -200,59 +952,125 @@ macro_rules! uart {
                 fn usart(&self) -> &USART {
                     return &self.sercom.usart();
                 }
-
-                fn dre(&self) -> bool {
-                    self.usart().intflag.read().dre().bit_is_set()
-                }
             }
 
 
             impl<RX, TX, RTS, CTS> serial::Write<u8> for $Type<RX, TX, RTS, CTS> {
-                type Error = ();
+                type Error = Error;
 
                 fn write(&mut self, word: u8) -> nb::Result<(), Self::Error> {
-                    unsafe {
-                        if !self.dre() {
-                            return Err(nb::Error::WouldBlock);
-                        }
+                    write_word(self.usart(), word as u16)
+                }
 
-                        self.sercom.usart().data.write(|w| {
-                            w.bits(word as u16)
-                        });
-                    }
+                fn flush(&mut self) -> nb::Result<(), Self::Error> {
+                    flush(self.usart())
+                }
+            }
 
-                    Ok(())
+            impl<RX, TX, RTS, CTS> serial::Read<u8> for $Type<RX, TX, RTS, CTS> {
+                type Error = Error;
+
+                fn read(&mut self) -> nb::Result<u8, Self::Error> {
+                    read_word(self.usart()).map(|word| word as u8)
                 }
+            }
 
-                fn flush(&mut self) -> nb::Result<(), Self::Error> {
-                    // simply await DRE empty
-                    if !self.dre() {
-                        return Err(nb::Error::WouldBlock);
-                    }
+            impl<RX, TX, RTS, CTS> Default<u8> for $Type<RX, TX, RTS, CTS> {}
 
-                    Ok(())
+            impl<RX, TX, RTS, CTS> fmt::Write for $Type<RX, TX, RTS, CTS> {
+                fn write_str(&mut self, s: &str) -> fmt::Result {
+                    self.bwrite_all(s.as_bytes()).map_err(|_| fmt::Error)
                 }
             }
 
-            impl<RX, TX, RTS, CTS> serial::Read<u8> for $Type<RX, TX, RTS, CTS> {
-                type Error = ();
+            /// The receiving half of a UARTX, obtained with `split`
+            pub struct [<$Type Rx>] {
+                _private: (),
+            }
+
+            /// The transmitting half of a UARTX, obtained with `split`
+            pub struct [<$Type Tx>] {
+                _private: (),
+            }
+
+            impl [<$Type Rx>] {
+                fn usart(&self) -> &USART {
+                    // The half has exclusive use of the receiver, and the
+                    // registers it shares with the transmitting half are
+                    // either write-1 or only read
+                    unsafe { (*$SERCOM::ptr()).usart() }
+                }
+
+                /// Read a character of up to 9 bits, as used with
+                /// `CharSize::Nine`
+                pub fn read_word(&mut self) -> nb::Result<u16, Error> {
+                    read_word(self.usart())
+                }
+            }
+
+            impl [<$Type Tx>] {
+                fn usart(&self) -> &USART {
+                    // See the receiving half
+                    unsafe { (*$SERCOM::ptr()).usart() }
+                }
+
+                /// Write a character of up to 9 bits, as used with
+                /// `CharSize::Nine`
+                pub fn write_word(&mut self, word: u16) -> nb::Result<(), Error> {
+                    write_word(self.usart(), word)
+                }
+
+                /// Returns false while hardware flow control is enabled
+                /// and the other end deasserts CTS, which pauses the
+                /// transmission
+                pub fn clear_to_send(&self) -> bool {
+                    clear_to_send(self.usart())
+                }
+            }
+
+            impl UartHalf for [<$Type Rx>] {
+                fn enable_interrupt(&mut self, interrupt: UartInterrupt) {
+                    enable_interrupt(self.usart(), interrupt);
+                }
+
+                fn disable_interrupt(&mut self, interrupt: UartInterrupt) {
+                    disable_interrupt(self.usart(), interrupt);
+                }
+            }
+
+            impl UartHalf for [<$Type Tx>] {
+                fn enable_interrupt(&mut self, interrupt: UartInterrupt) {
+                    enable_interrupt(self.usart(), interrupt);
+                }
+
+                fn disable_interrupt(&mut self, interrupt: UartInterrupt) {
+                    disable_interrupt(self.usart(), interrupt);
+                }
+            }
+
+            impl serial::Read<u8> for [<$Type Rx>] {
+                type Error = Error;
 
                 fn read(&mut self) -> nb::Result<u8, Self::Error> {
-                    let has_data = self.usart().intflag.read().rxc().bit_is_set();
+                    read_word(self.usart()).map(|word| word as u8)
+                }
+            }
 
-                    if !has_data {
-                        return Err(nb::Error::WouldBlock);
-                    }
+            impl serial::Write<u8> for [<$Type Tx>] {
+                type Error = Error;
 
-                    let data = self.usart().data.read().bits();
+                fn write(&mut self, word: u8) -> nb::Result<(), Self::Error> {
+                    write_word(self.usart(), word as u16)
+                }
 
-                    Ok(data as u8)
+                fn flush(&mut self) -> nb::Result<(), Self::Error> {
+                    flush(self.usart())
                 }
             }
 
-            impl<RX, TX, RTS, CTS> Default<u8> for $Type<RX, TX, RTS, CTS> {}
+            impl Default<u8> for [<$Type Tx>] {}
 
-            impl<RX, TX, RTS, CTS> fmt::Write for $Type<RX, TX, RTS, CTS> {
+            impl fmt::Write for [<$Type Tx>] {
                 fn write_str(&mut self, s: &str) -> fmt::Result {
                     self.bwrite_all(s.as_bytes()).map_err(|_| fmt::Error)
                 }
@@ -280,3 +1098,64 @@ fn calculate_baud_value(baudrate: u32, clk_freq: u32, n_samples: u8) -> u16 {
 
     return baud_calculated as u16;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fractional_baud_setting() {
+        // 48MHz / (16 * 9600) = 312.5, so BAUD = 312 and FP = 4
+        assert_eq!(
+            SampleRate::Fractional16.baud_setting(48_000_000, 9600),
+            Some((312 | 4 << 13, 9600))
+        );
+        // BAUD only has 13 bits in fractional mode
+        assert_eq!(SampleRate::Fractional16.baud_setting(48_000_000, 300), None);
+    }
+
+    #[test]
+    fn arithmetic_baud_setting() {
+        assert_eq!(SampleRate::Arithmetic8.baud_setting(8_000_000, 1_000_000), Some((0, 1_000_000)));
+        assert_eq!(SampleRate::Arithmetic16.baud_setting(8_000_000, 1_000_000), None);
+    }
+
+    #[test]
+    fn auto_prefers_high_oversampling_within_tolerance() {
+        // 3x oversampling comes closer, but 16x is well within 1%
+        let (rate, _, actual) = SampleRate::Auto.resolve(48_000_000, 57_600).unwrap();
+        assert_eq!(rate, SampleRate::Arithmetic16);
+        assert_eq!(actual, 57_632);
+
+        // Of the 16x settings, the closer one wins
+        let (rate, _, actual) = SampleRate::Auto.resolve(8_000_000, 2400).unwrap();
+        assert_eq!(rate, SampleRate::Fractional16);
+        assert_eq!(actual, 2399);
+
+        let clocks = [8_000_000, 32_768_000, 48_000_000, 120_000_000];
+        let bauds = [1200, 9600, 19_200, 57_600, 115_200, 230_400, 460_800, 921_600];
+        for &fref in clocks.iter() {
+            for &baud in bauds.iter() {
+                let (rate, _, actual) = SampleRate::Auto.resolve(fref, baud).unwrap();
+                assert!((actual as i64 - baud as i64).abs() * 100 <= baud as i64);
+                if fref >= 16 * baud {
+                    assert_eq!(rate.samples(), 16, "{} from {}", baud, fref);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn auto_falls_back_to_closest() {
+        // Nothing gets within 1%, and 16x arithmetic comes closest
+        let (rate, _, actual) = SampleRate::Auto.resolve(48_000_000, 300).unwrap();
+        assert_eq!(rate, SampleRate::Arithmetic16);
+        assert_eq!(actual, 320);
+
+        // Only 8x and 3x reach 1Mbaud from 8MHz
+        let (rate, value, actual) = SampleRate::Auto.resolve(8_000_000, 1_000_000).unwrap();
+        assert_eq!((rate, value, actual), (SampleRate::Arithmetic8, 0, 1_000_000));
+
+        assert_eq!(SampleRate::Auto.resolve(1_000_000, 1_000_000), Err(ConfigError::BaudRate));
+    }
+}
@@ -0,0 +1,198 @@
+//! Working with the Watchdog Timer (WDT).
+//!
+//! The watchdog resets the device unless it is fed within the configured
+//! timeout period.  The period is counted in cycles of the `WdtClock`,
+//! which is normally a 1.024kHz generator derived from OSCULP32K.
+//!
+//! ```no_run
+//! let gclk2 = clocks
+//!     .configure_gclk_divider_and_source(ClockGenId::GCLK2, 32, ClockSource::OSCULP32K, false)
+//!     .unwrap();
+//! let wdt_clock = clocks.wdt(&gclk2).unwrap();
+//! let mut watchdog = Watchdog::new(peripherals.WDT, &wdt_clock);
+//! watchdog.start(WatchdogTimeout::Cycles8K);
+//! loop {
+//!     watchdog.feed();
+//! }
+//! ```
+use crate::clock::WdtClock;
+use crate::target_device::WDT;
+use crate::time::Hertz;
+use hal::watchdog;
+
+/// The number of watchdog clock cycles in a timeout, window or early
+/// warning period
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WatchdogTimeout {
+    Cycles8 = 0,
+    Cycles16,
+    Cycles32,
+    Cycles64,
+    Cycles128,
+    Cycles256,
+    Cycles512,
+    Cycles1K,
+    Cycles2K,
+    Cycles4K,
+    Cycles8K,
+    Cycles16K,
+}
+
+impl WatchdogTimeout {
+    /// Returns the number of clock cycles in the period
+    pub fn cycles(self) -> u32 {
+        8 << self as u32
+    }
+}
+
+/// `Watchdog` encapsulates the WDT hardware.  It implements the
+/// `Watchdog`, `WatchdogEnable` and `WatchdogDisable` embedded_hal traits.
+pub struct Watchdog {
+    wdt: WDT,
+    freq: Hertz,
+}
+
+impl Watchdog {
+    /// Take ownership of the WDT.  The `WdtClock` clocks the watchdog
+    /// counter; the watchdog is left in its current state, which may
+    /// already be enabled by the fuses.
+    pub fn new(wdt: WDT, clock: &WdtClock) -> Self {
+        Self {
+            wdt,
+            freq: clock.freq(),
+        }
+    }
+
+    fn sync(&self) {
+        while self.wdt.status.read().syncbusy().bit_is_set() {}
+    }
+
+    fn configure(&mut self, timeout: WatchdogTimeout, window: Option<WatchdogTimeout>) {
+        assert!(!self.is_always_on(), "the watchdog is locked always-on");
+
+        // The configuration can only be changed while disabled
+        self.wdt.ctrl.write(|w| w.enable().clear_bit());
+        self.sync();
+        self.wdt.config.write(|w| unsafe {
+            w.per().bits(timeout as u8);
+            w.window().bits(window.unwrap_or(WatchdogTimeout::Cycles8) as u8)
+        });
+    }
+
+    /// Start the watchdog in window mode.  The watchdog must be fed
+    /// after the `window` period has elapsed, and within the following
+    /// `timeout` period; feeding too early also resets the device.
+    pub fn start_window(&mut self, window: WatchdogTimeout, timeout: WatchdogTimeout) {
+        self.configure(timeout, Some(window));
+        self.wdt.ctrl.write(|w| {
+            w.wen().set_bit();
+            w.enable().set_bit()
+        });
+        self.sync();
+    }
+
+    /// Start the watchdog and lock it on.  The watchdog, and its
+    /// configuration, can then only be changed by a reset.
+    pub fn start_always_on(&mut self, timeout: WatchdogTimeout) {
+        self.configure(timeout, None);
+        self.wdt.ctrl.write(|w| w.alwayson().set_bit());
+        self.sync();
+    }
+
+    /// Returns true if the watchdog has been locked on
+    pub fn is_always_on(&self) -> bool {
+        self.wdt.ctrl.read().alwayson().bit_is_set()
+    }
+
+    /// Returns true if the watchdog is running
+    pub fn is_enabled(&self) -> bool {
+        let ctrl = self.wdt.ctrl.read();
+        ctrl.enable().bit_is_set() || ctrl.alwayson().bit_is_set()
+    }
+
+    /// Returns the duration of `timeout` in milliseconds
+    pub fn timeout_ms(&self, timeout: WatchdogTimeout) -> u32 {
+        (timeout.cycles() as u64 * 1_000 / self.freq.0 as u64) as u32
+    }
+
+    /// Set the number of cycles after the start of the timeout period
+    /// at which the early warning interrupt is raised.  In window mode the
+    /// offset is counted from the start of the window period.
+    pub fn set_early_warning(&mut self, offset: WatchdogTimeout) {
+        // EWCTRL is enable protected
+        let enabled = self.wdt.ctrl.read().enable().bit_is_set();
+        if enabled {
+            self.wdt.ctrl.modify(|_, w| w.enable().clear_bit());
+            self.sync();
+        }
+        self.wdt
+            .ewctrl
+            .write(|w| unsafe { w.ewoffset().bits(offset as u8) });
+        if enabled {
+            self.wdt.ctrl.modify(|_, w| w.enable().set_bit());
+            self.sync();
+        }
+    }
+
+    /// Enable the early warning interrupt.
+    /// This method only configures the watchdog to raise the interrupt;
+    /// it does not configure the interrupt controller or define an
+    /// interrupt handler.
+    pub fn enable_early_warning_interrupt(&mut self) {
+        self.wdt.intenset.write(|w| w.ew().set_bit());
+    }
+
+    /// Disables the early warning interrupt
+    pub fn disable_early_warning_interrupt(&mut self) {
+        self.wdt.intenclr.write(|w| w.ew().set_bit());
+    }
+
+    /// Returns true if the early warning has been raised since the flag
+    /// was last cleared
+    pub fn is_early_warning(&self) -> bool {
+        self.wdt.intflag.read().ew().bit_is_set()
+    }
+
+    /// Clear the early warning flag
+    pub fn clear_early_warning(&mut self) {
+        // Writing a 1 clears the flag
+        self.wdt.intflag.write(|w| w.ew().set_bit());
+    }
+
+    /// Release the WDT.  The watchdog is left running if it was enabled.
+    pub fn free(self) -> WDT {
+        self.wdt
+    }
+}
+
+impl watchdog::Watchdog for Watchdog {
+    fn feed(&mut self) {
+        // Writing anything other than the key resets the device
+        self.wdt.clear.write(|w| w.clear().key());
+        self.sync();
+    }
+}
+
+impl watchdog::WatchdogEnable for Watchdog {
+    type Time = WatchdogTimeout;
+
+    /// Start the watchdog in normal mode; it must be fed within the
+    /// `period`.
+    fn start<T>(&mut self, period: T)
+    where
+        T: Into<WatchdogTimeout>,
+    {
+        self.configure(period.into(), None);
+        self.wdt.ctrl.write(|w| w.enable().set_bit());
+        self.sync();
+    }
+}
+
+impl watchdog::WatchdogDisable for Watchdog {
+    /// Stop the watchdog.  Panics if the watchdog has been locked on.
+    fn disable(&mut self) {
+        assert!(!self.is_always_on(), "the watchdog is locked always-on");
+        self.wdt.ctrl.write(|w| w.enable().clear_bit());
+        self.sync();
+    }
+}
@@ -0,0 +1,329 @@
+//! Input capture with the timer counter hardware.
+//!
+//! A `TimerCounter` can be turned into one of two kinds of capture:
+//!
+//! * `PulseCapture` measures the period and pulse width of a signal, from
+//!   which its frequency and duty cycle follow.  This suits RC receivers,
+//!   ultrasonic rangefinders and tachometers.
+//! * `TimestampCapture` records the value of a free running counter when
+//!   an event occurs.
+//!
+//! The captured signal is fed to the TC by the event system, for example
+//! from an external interrupt line with `ExtIntN::enable_event`; the event
+//! channel must be routed to the TC before the capture produces results.
+//! Results are reported in ticks of the prescaled counter, whose frequency
+//! is returned by `tick_freq`.
+//!
+//! ```no_run
+//! let gclk0 = clocks.gclk0();
+//! let tc23 = clocks.tc2_tc3(&gclk0).unwrap();
+//! let timer = TimerCounter::tc3_(&tc23, peripherals.TC3, &mut peripherals.MCLK);
+//! // Measure signals down to 40Hz
+//! let mut capture = PulseCapture::new(timer, 40.hz(), CaptureMode::PeriodPulseWidth, false).unwrap();
+//! let pulse = nb::block!(capture.read()).unwrap();
+//! let width_us = capture.ticks_to_us(pulse.pulse_width);
+//! ```
+use crate::target_device::tc0::COUNT16;
+use crate::time::Hertz;
+use crate::timer::{self, Count16, TimerCounter, TimerParams};
+
+/// Errors that may occur reading a capture
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error {
+    /// A new value was captured before the previous one was read, and
+    /// the previous one was lost
+    Overrun,
+    /// The counter wrapped before the signal changed; the period is too
+    /// long to be measured with the chosen prescaler, or the signal is
+    /// not present
+    Overflow,
+}
+
+/// The order in which the period and the pulse width are captured
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CaptureMode {
+    /// The period is captured into CC0 on the start edge of the pulse,
+    /// and the pulse width into CC1 on its end edge (PPW)
+    PeriodPulseWidth,
+    /// The pulse width is captured into CC0 and the period into CC1 (PWP)
+    PulseWidthPeriod,
+}
+
+/// The CC channel a timestamp is captured into
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CaptureChannel {
+    C0,
+    C1,
+}
+
+/// A captured period and pulse width, in ticks of the counter
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pulse {
+    /// The number of ticks between two start edges
+    pub period: u16,
+    /// The number of ticks between the start edge and the end edge
+    pub pulse_width: u16,
+}
+
+/// Reset the counter and configure it to count at the prescaled
+/// frequency, returning the tick frequency.  The counter is left alone
+/// if `min_freq` is out of range.
+fn reset(count: &COUNT16, freq: Hertz, min_freq: Hertz) -> Result<Hertz, timer::Error> {
+    let divider = TimerParams::try_new(min_freq, freq.0)?.divider;
+
+    count.ctrla.modify(|_, w| w.enable().clear_bit());
+    while count.syncbusy.read().bits() != 0 {}
+    count.ctrla.write(|w| w.swrst().set_bit());
+    while count.syncbusy.read().bits() != 0 {}
+    // the SVD erroneously marks swrst as write-only, so we
+    // need to manually read the bit here
+    while count.ctrla.read().bits() & 1 != 0 {}
+
+    count.ctrla.modify(|_, w| match divider {
+        1 => w.prescaler().div1(),
+        2 => w.prescaler().div2(),
+        4 => w.prescaler().div4(),
+        8 => w.prescaler().div8(),
+        16 => w.prescaler().div16(),
+        64 => w.prescaler().div64(),
+        256 => w.prescaler().div256(),
+        1024 => w.prescaler().div1024(),
+        _ => unreachable!(),
+    });
+    Ok(Hertz(freq.0 / divider as u32))
+}
+
+fn enable(count: &COUNT16) {
+    // Discard anything captured or flagged before the start
+    count.intflag.write(|w| {
+        w.ovf().set_bit();
+        w.err().set_bit();
+        w.mc0().set_bit();
+        w.mc1().set_bit()
+    });
+    count.ctrla.modify(|_, w| w.enable().set_bit());
+    while count.syncbusy.read().bits() != 0 {}
+}
+
+/// Read a CC register
+fn read_cc(count: &COUNT16, channel: usize) -> u16 {
+    count.cc[channel].read().cc().bits()
+}
+
+/// `PulseCapture` measures the period and pulse width of the signal fed
+/// to a timer counter through the event system.
+pub struct PulseCapture<TC> {
+    timer: TimerCounter<TC>,
+    tick_freq: Hertz,
+    mode: CaptureMode,
+}
+
+impl<TC> PulseCapture<TC>
+where
+    TC: Count16,
+{
+    /// Start capturing.  The counter is prescaled so that periods down to
+    /// `min_freq` can be measured.  The pulse starts on the rising edge
+    /// of the event, or on the falling edge if `inverted` is set.
+    ///
+    /// Returns an error with the achievable range if `min_freq` is out of
+    /// range for the counter.
+    pub fn new<F: Into<Hertz>>(
+        timer: TimerCounter<TC>,
+        min_freq: F,
+        mode: CaptureMode,
+        inverted: bool,
+    ) -> Result<Self, timer::Error> {
+        let count = timer.tc.count_16();
+        let tick_freq = reset(count, timer.freq, min_freq.into())?;
+        count.evctrl.write(|w| {
+            match mode {
+                CaptureMode::PeriodPulseWidth => w.evact().ppw(),
+                CaptureMode::PulseWidthPeriod => w.evact().pwp(),
+            };
+            w.tcinv().bit(inverted);
+            w.tcei().set_bit()
+        });
+        count.ctrla.modify(|_, w| {
+            w.capten0().set_bit();
+            w.capten1().set_bit()
+        });
+        enable(count);
+
+        Ok(Self {
+            timer,
+            tick_freq,
+            mode,
+        })
+    }
+
+    /// Returns the frequency of the counter ticks
+    pub fn tick_freq(&self) -> Hertz {
+        self.tick_freq
+    }
+
+    /// Convert a number of ticks into microseconds
+    pub fn ticks_to_us(&self, ticks: u16) -> u32 {
+        (ticks as u64 * 1_000_000 / self.tick_freq.0 as u64) as u32
+    }
+
+    /// Returns the latest period and pulse width once both have been
+    /// captured
+    pub fn read(&mut self) -> nb::Result<Pulse, Error> {
+        let count = self.timer.tc.count_16();
+        let flags = count.intflag.read();
+        if flags.err().bit_is_set() {
+            count.intflag.write(|w| w.err().set_bit());
+            return Err(nb::Error::Other(Error::Overrun));
+        }
+        if flags.ovf().bit_is_set() {
+            count.intflag.write(|w| w.ovf().set_bit());
+            return Err(nb::Error::Other(Error::Overflow));
+        }
+        if flags.mc0().bit_is_clear() || flags.mc1().bit_is_clear() {
+            return Err(nb::Error::WouldBlock);
+        }
+
+        // Reading the CC registers clears the flags
+        let first = read_cc(count, 0);
+        let second = read_cc(count, 1);
+        Ok(match self.mode {
+            CaptureMode::PeriodPulseWidth => Pulse {
+                period: first,
+                pulse_width: second,
+            },
+            CaptureMode::PulseWidthPeriod => Pulse {
+                period: second,
+                pulse_width: first,
+            },
+        })
+    }
+
+    /// Returns the frequency of the signal once a period has been
+    /// captured
+    pub fn read_frequency(&mut self) -> nb::Result<Hertz, Error> {
+        let pulse = self.read()?;
+        Ok(Hertz(self.tick_freq.0 / (pulse.period as u32).max(1)))
+    }
+
+    /// Enable the interrupt raised once a period and pulse width have
+    /// been captured.
+    /// This method only configures the timer to raise the interrupt;
+    /// it does not configure the interrupt controller or define an
+    /// interrupt handler.
+    pub fn enable_interrupt(&mut self) {
+        self.timer.tc.count_16().intenset.write(|w| w.mc1().set_bit());
+    }
+
+    /// Disable the capture interrupt
+    pub fn disable_interrupt(&mut self) {
+        self.timer.tc.count_16().intenclr.write(|w| w.mc1().set_bit());
+    }
+
+    /// Stop capturing and release the timer
+    pub fn free(self) -> TimerCounter<TC> {
+        let count = self.timer.tc.count_16();
+        count.ctrla.modify(|_, w| w.enable().clear_bit());
+        while count.syncbusy.read().bits() != 0 {}
+        self.timer
+    }
+}
+
+/// `TimestampCapture` records the value of a free running counter when
+/// the event fed to the timer counter through the event system occurs.
+pub struct TimestampCapture<TC> {
+    timer: TimerCounter<TC>,
+    tick_freq: Hertz,
+}
+
+impl<TC> TimestampCapture<TC>
+where
+    TC: Count16,
+{
+    /// Start capturing on both CC channels.  The counter is prescaled so
+    /// that it wraps no more often than `wrap_freq`.  Timestamps are taken
+    /// on the rising edge of the event, or on the falling edge if
+    /// `inverted` is set.
+    ///
+    /// Returns an error with the achievable range if `wrap_freq` is out
+    /// of range for the counter.
+    pub fn new<F: Into<Hertz>>(
+        timer: TimerCounter<TC>,
+        wrap_freq: F,
+        inverted: bool,
+    ) -> Result<Self, timer::Error> {
+        let count = timer.tc.count_16();
+        let tick_freq = reset(count, timer.freq, wrap_freq.into())?;
+        count.evctrl.write(|w| {
+            w.evact().off();
+            w.tcinv().bit(inverted);
+            w.tcei().set_bit()
+        });
+        count.ctrla.modify(|_, w| {
+            w.capten0().set_bit();
+            w.capten1().set_bit()
+        });
+        enable(count);
+
+        Ok(Self { timer, tick_freq })
+    }
+
+    /// Returns the frequency of the counter ticks
+    pub fn tick_freq(&self) -> Hertz {
+        self.tick_freq
+    }
+
+    /// Returns the current value of the counter
+    pub fn count(&self) -> u16 {
+        let count = self.timer.tc.count_16();
+        count.ctrlbset.write(|w| w.cmd().readsync());
+        while count.syncbusy.read().ctrlb().bit_is_set() {}
+        count.count.read().count().bits()
+    }
+
+    /// Returns the timestamp captured by `channel`
+    pub fn read(&mut self, channel: CaptureChannel) -> nb::Result<u16, Error> {
+        let count = self.timer.tc.count_16();
+        let flags = count.intflag.read();
+        if flags.err().bit_is_set() {
+            count.intflag.write(|w| w.err().set_bit());
+            return Err(nb::Error::Other(Error::Overrun));
+        }
+        let (captured, index) = match channel {
+            CaptureChannel::C0 => (flags.mc0().bit_is_set(), 0),
+            CaptureChannel::C1 => (flags.mc1().bit_is_set(), 1),
+        };
+        if !captured {
+            return Err(nb::Error::WouldBlock);
+        }
+        // Reading the CC register clears the flag
+        Ok(read_cc(count, index))
+    }
+
+    /// Enable the interrupt raised when `channel` captures a timestamp.
+    /// This method only configures the timer to raise the interrupt;
+    /// it does not configure the interrupt controller or define an
+    /// interrupt handler.
+    pub fn enable_interrupt(&mut self, channel: CaptureChannel) {
+        self.timer.tc.count_16().intenset.write(|w| match channel {
+            CaptureChannel::C0 => w.mc0().set_bit(),
+            CaptureChannel::C1 => w.mc1().set_bit(),
+        });
+    }
+
+    /// Disable the capture interrupt of `channel`
+    pub fn disable_interrupt(&mut self, channel: CaptureChannel) {
+        self.timer.tc.count_16().intenclr.write(|w| match channel {
+            CaptureChannel::C0 => w.mc0().set_bit(),
+            CaptureChannel::C1 => w.mc1().set_bit(),
+        });
+    }
+
+    /// Stop capturing and release the timer
+    pub fn free(self) -> TimerCounter<TC> {
+        let count = self.timer.tc.count_16();
+        count.ctrla.modify(|_, w| w.enable().clear_bit());
+        while count.syncbusy.read().bits() != 0 {}
+        self.timer
+    }
+}
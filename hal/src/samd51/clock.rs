@@ -367,6 +367,9 @@ impl GenericClockController {
 }
 
 clock_generator!(
+    (tcc0_tcc1, Tcc0Tcc1Clock, TCC0_TCC1),
+    (tcc2_tcc3, Tcc2Tcc3Clock, TCC2_TCC3),
+    (tcc4, Tcc4Clock, TCC4),
     (tc0_tc1, Tc0Tc1Clock, TC0_TC1),
     (tc2_tc3, Tc2Tc3Clock, TC2_TC3),
     (tc4_tc5, Tc4Tc5Clock, TC4_TC5),
@@ -380,6 +383,8 @@ clock_generator!(
     (usb, UsbClock, USB),
     (adc0, Adc0Clock, ADC0),
     (adc1, Adc1Clock, ADC1),
+    (eic, EicClock, EIC),
+    (dac, DacClock, DAC),
 );
 
 /// The frequency of the 48Mhz source.
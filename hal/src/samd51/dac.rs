@@ -0,0 +1,461 @@
+//! Working with the Digital-to-Analog Converter (DAC).
+//!
+//! The DAC has two 12-bit channels, driving VOUT0 on PA02 and VOUT1 on
+//! PA05.  The output pins are tracked in the type of `Dac`: a channel is
+//! enabled by handing its pin to `enable_channel0` or `enable_channel1`,
+//! after which the channel can be written.
+//!
+//! Besides writing values directly, conversions can be paced by an event:
+//! with the start event of a channel enabled, each event moves the value
+//! in its data buffer into the converter.  The data buffer can be refilled
+//! by the DMAC, so that a timer routed through the event system plays
+//! back a buffer of samples without involving the CPU.
+//!
+//! ```no_run
+//! let gclk0 = clocks.gclk0();
+//! let gclk4 = clocks
+//!     .configure_gclk_divider_and_source(ClockGenId::GCLK4, 10, ClockSource::DFLL, false)
+//!     .unwrap();
+//! let dac_clock = clocks.dac(&gclk4).unwrap();
+//! let vout0 = pins.a0.into_function_b(&mut pins.port);
+//! let mut dac = Dac::init(peripherals.DAC, &mut peripherals.MCLK, &dac_clock, Reference::Vddana)
+//!     .enable_channel0(vout0, ChannelConfig::default());
+//! dac.write0(MAX_VALUE / 2);
+//! ```
+use crate::clock::DacClock;
+use crate::dmac::{self, BeatSize, Channel, ChannelId, Priority, TriggerAction, TriggerSource};
+use crate::gpio::{Pa2, Pa5, PfB};
+use crate::target_device::{DAC, MCLK};
+use crate::time::Hertz;
+
+/// The largest value that can be converted
+pub const MAX_VALUE: u16 = 0xfff;
+
+/// The highest supported frequency of the DAC clock
+pub const MAX_CLOCK_FREQ: Hertz = Hertz(12_000_000);
+
+/// The voltage reference, which sets the output for `MAX_VALUE`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reference {
+    /// The external reference on VREFA, unbuffered
+    External,
+    /// The analog supply voltage
+    Vddana,
+    /// The external reference on VREFA, buffered
+    ExternalBuffered,
+    /// The internal bandgap reference
+    Internal,
+}
+
+/// The oversampling ratio of the interpolation filter
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Oversampling {
+    None = 0,
+    Osr2,
+    Osr4,
+    Osr8,
+    Osr16,
+    Osr32,
+}
+
+/// The configuration of a DAC channel
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChannelConfig {
+    /// The refresh period of the output in units of 30us, to compensate
+    /// for the droop of the output; 0 disables refreshing
+    pub refresh: u8,
+    /// Enable dithering, which adds 4 bits of resolution when used with
+    /// `oversampling`
+    pub dither: bool,
+    /// The oversampling ratio of the interpolation filter
+    pub oversampling: Oversampling,
+    /// Use an external filter on the output, bypassing the internal
+    /// output buffer
+    pub external_filter: bool,
+    /// Keep the channel running in standby sleep mode
+    pub run_in_standby: bool,
+    /// Take written values as left adjusted, so that the 12 most
+    /// significant bits of a 16-bit value are converted
+    pub left_adjusted: bool,
+}
+
+impl Default for ChannelConfig {
+    fn default() -> Self {
+        ChannelConfig {
+            refresh: 0,
+            dither: false,
+            oversampling: Oversampling::None,
+            external_filter: false,
+            run_in_standby: false,
+            left_adjusted: false,
+        }
+    }
+}
+
+/// The DAC interrupts that can be enabled or inspected, per channel
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Interrupt {
+    /// The data buffer is empty and may be written
+    Empty,
+    /// A start event occurred while the data buffer was empty
+    Underrun,
+    /// The result of the interpolation filter is ready
+    ResultReady,
+    /// A start event occurred before the previous conversion completed
+    Overrun,
+}
+
+impl Interrupt {
+    fn mask(self, channel: u8) -> u8 {
+        let shift = match self {
+            Interrupt::Underrun => 0,
+            Interrupt::Empty => 2,
+            Interrupt::ResultReady => 4,
+            Interrupt::Overrun => 6,
+        };
+        1 << (shift + channel)
+    }
+}
+
+/// The type state of a channel whose output is disabled
+pub struct NoOutput;
+
+/// Errors starting a DMA write
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error {
+    /// The buffer is empty, or longer than the 65535 samples a DMA
+    /// descriptor can move
+    BufferLength,
+}
+
+/// A write of a buffer of samples driven by the DMAC, returned by
+/// `Dac::dma_write0` or `Dac::dma_write1`.
+///
+/// Poll the transfer with `poll` or block on it with `wait`, then use
+/// `free` to get back the DAC, the DMA channel and the buffer.
+pub struct DacDmaWrite<DAC, CH> {
+    dac: DAC,
+    channel: Channel<CH>,
+    buffer: &'static [u16],
+}
+
+/// `Dac` encapsulates the DAC hardware, and the output pins of the
+/// enabled channels.
+pub struct Dac<VOUT0, VOUT1> {
+    dac: DAC,
+    vout0: VOUT0,
+    vout1: VOUT1,
+    freq: Hertz,
+}
+
+impl Dac<NoOutput, NoOutput> {
+    /// Configure the DAC to use the `reference` voltage, with both
+    /// channels disabled.  Panics if the DAC clock is faster than
+    /// `MAX_CLOCK_FREQ`.
+    pub fn init(dac: DAC, mclk: &mut MCLK, clock: &DacClock, reference: Reference) -> Self {
+        let freq = clock.freq();
+        assert!(freq.0 <= MAX_CLOCK_FREQ.0, "the DAC clock must not exceed 12MHz");
+        mclk.apbdmask.modify(|_, w| w.dac_().set_bit());
+
+        dac.ctrla.write(|w| w.swrst().set_bit());
+        while dac.syncbusy.read().swrst().bit_is_set() {}
+
+        dac.ctrlb.write(|w| match reference {
+            Reference::External => w.refsel().vrefpu(),
+            Reference::Vddana => w.refsel().vddana(),
+            Reference::ExternalBuffered => w.refsel().vrefpb(),
+            Reference::Internal => w.refsel().intref(),
+        });
+
+        Self {
+            dac,
+            vout0: NoOutput,
+            vout1: NoOutput,
+            freq,
+        }
+    }
+}
+
+impl<VOUT0, VOUT1> Dac<VOUT0, VOUT1> {
+    /// Returns the frequency of the DAC clock
+    pub fn clock_freq(&self) -> Hertz {
+        self.freq
+    }
+
+    /// Run `f` with the DAC disabled, as required to change the
+    /// enable-protected configuration, and wait for the enabled channels
+    /// to become ready again
+    fn disabled<F: FnOnce(&DAC)>(&mut self, f: F) {
+        self.dac.ctrla.modify(|_, w| w.enable().clear_bit());
+        while self.dac.syncbusy.read().enable().bit_is_set() {}
+        f(&self.dac);
+
+        let channels = self.dac.dacctrl[0].read().enable().bit_is_set() as u8
+            | (self.dac.dacctrl[1].read().enable().bit_is_set() as u8) << 1;
+        if channels == 0 {
+            return;
+        }
+        self.dac.ctrla.modify(|_, w| w.enable().set_bit());
+        while self.dac.syncbusy.read().enable().bit_is_set() {}
+        // The READY bits follow the enabled channels
+        while self.dac.status.read().bits() & channels != channels {}
+    }
+
+    fn configure_channel(&mut self, channel: usize, config: Option<ChannelConfig>) {
+        let cctrl = if self.freq.0 <= 1_200_000 {
+            0
+        } else if self.freq.0 <= 6_000_000 {
+            1
+        } else {
+            2
+        };
+        self.disabled(|dac| match config {
+            Some(config) => dac.dacctrl[channel].write(|w| unsafe {
+                w.leftadj().bit(config.left_adjusted);
+                w.cctrl().bits(cctrl);
+                w.fext().bit(config.external_filter);
+                w.runstdby().bit(config.run_in_standby);
+                w.dither().bit(config.dither);
+                w.refresh().bits(config.refresh);
+                w.osr().bits(config.oversampling as u8);
+                w.enable().set_bit()
+            }),
+            None => dac.dacctrl[channel].reset(),
+        });
+    }
+
+    /// Select the voltage reference
+    pub fn set_reference(&mut self, reference: Reference) {
+        self.disabled(|dac| {
+            dac.ctrlb.modify(|_, w| match reference {
+                Reference::External => w.refsel().vrefpu(),
+                Reference::Vddana => w.refsel().vddana(),
+                Reference::ExternalBuffered => w.refsel().vrefpb(),
+                Reference::Internal => w.refsel().intref(),
+            })
+        });
+    }
+
+    fn set_event_control(&mut self, mask: u8, enabled: bool) {
+        self.disabled(|dac| {
+            dac.evctrl.modify(|r, w| unsafe {
+                if enabled {
+                    w.bits(r.bits() | mask)
+                } else {
+                    w.bits(r.bits() & !mask)
+                }
+            })
+        });
+    }
+
+    /// Enable the interrupt generation for `interrupt` of `channel`.
+    /// This method only configures the DAC to raise the interrupt;
+    /// it does not configure the interrupt controller or define an
+    /// interrupt handler.
+    pub fn enable_interrupt(&mut self, channel: u8, interrupt: Interrupt) {
+        self.dac
+            .intenset
+            .write(|w| unsafe { w.bits(interrupt.mask(channel)) });
+    }
+
+    /// Disable the interrupt generation for `interrupt` of `channel`
+    pub fn disable_interrupt(&mut self, channel: u8, interrupt: Interrupt) {
+        self.dac
+            .intenclr
+            .write(|w| unsafe { w.bits(interrupt.mask(channel)) });
+    }
+
+    /// Returns true if the flag of `interrupt` of `channel` is set
+    pub fn is_interrupt_set(&self, channel: u8, interrupt: Interrupt) -> bool {
+        self.dac.intflag.read().bits() & interrupt.mask(channel) != 0
+    }
+
+    /// Clear the flag of `interrupt` of `channel`
+    pub fn clear_interrupt(&mut self, channel: u8, interrupt: Interrupt) {
+        // Writing a 1 clears the flag
+        self.dac
+            .intflag
+            .write(|w| unsafe { w.bits(interrupt.mask(channel)) });
+    }
+}
+
+impl Dac<NoOutput, NoOutput> {
+    /// Release the DAC
+    pub fn free(self, mclk: &mut MCLK) -> DAC {
+        self.dac.ctrla.write(|w| w.swrst().set_bit());
+        while self.dac.syncbusy.read().swrst().bit_is_set() {}
+        mclk.apbdmask.modify(|_, w| w.dac_().clear_bit());
+        self.dac
+    }
+}
+
+impl<VOUT1> Dac<NoOutput, VOUT1> {
+    /// Enable channel 0 with `config`, driving VOUT0.  The DAC is
+    /// briefly disabled, interrupting channel 1.
+    pub fn enable_channel0(mut self, vout0: Pa2<PfB>, config: ChannelConfig) -> Dac<Pa2<PfB>, VOUT1> {
+        self.configure_channel(0, Some(config));
+        Dac {
+            dac: self.dac,
+            vout0,
+            vout1: self.vout1,
+            freq: self.freq,
+        }
+    }
+}
+
+impl<VOUT1> Dac<Pa2<PfB>, VOUT1> {
+    /// Disable channel 0 and release VOUT0
+    pub fn disable_channel0(mut self) -> (Dac<NoOutput, VOUT1>, Pa2<PfB>) {
+        self.configure_channel(0, None);
+        let dac = Dac {
+            dac: self.dac,
+            vout0: NoOutput,
+            vout1: self.vout1,
+            freq: self.freq,
+        };
+        (dac, self.vout0)
+    }
+}
+
+impl<VOUT0> Dac<VOUT0, NoOutput> {
+    /// Enable channel 1 with `config`, driving VOUT1.  The DAC is
+    /// briefly disabled, interrupting channel 0.
+    pub fn enable_channel1(mut self, vout1: Pa5<PfB>, config: ChannelConfig) -> Dac<VOUT0, Pa5<PfB>> {
+        self.configure_channel(1, Some(config));
+        Dac {
+            dac: self.dac,
+            vout0: self.vout0,
+            vout1,
+            freq: self.freq,
+        }
+    }
+}
+
+impl<VOUT0> Dac<VOUT0, Pa5<PfB>> {
+    /// Disable channel 1 and release VOUT1
+    pub fn disable_channel1(mut self) -> (Dac<VOUT0, NoOutput>, Pa5<PfB>) {
+        self.configure_channel(1, None);
+        let dac = Dac {
+            dac: self.dac,
+            vout0: self.vout0,
+            vout1: NoOutput,
+            freq: self.freq,
+        };
+        (dac, self.vout1)
+    }
+}
+
+macro_rules! dac_channel {
+    (
+        $num:expr, $G:ident, $VOUT0:ty, $VOUT1:ty, $TRIGGER:ident,
+        ($startei:expr, $invei:expr, $emptyeo:expr, $resrdyeo:expr),
+        ($data_busy:expr, $databuf_busy:expr),
+        $write:ident, $write_buffer:ident, $set_start_event:ident,
+        $set_output_events:ident, $dma_write:ident
+    ) => {
+        impl<$G> Dac<$VOUT0, $VOUT1> {
+            /// Convert `value` on this channel, which must not exceed
+            /// `MAX_VALUE` unless the channel is left adjusted
+            pub fn $write(&mut self, value: u16) {
+                while self.dac.syncbusy.read().bits() & $data_busy != 0 {}
+                self.dac.data[$num].write(|w| unsafe { w.data().bits(value) });
+            }
+
+            /// Write `value` to the data buffer of this channel, to be
+            /// converted on the next start event
+            pub fn $write_buffer(&mut self, value: u16) {
+                while self.dac.syncbusy.read().bits() & $databuf_busy != 0 {}
+                self.dac.databuf[$num].write(|w| unsafe { w.databuf().bits(value) });
+            }
+
+            /// Start a conversion of the data buffer of this channel on
+            /// each incoming event, or on each inverted event if `inverted`
+            /// is set.  This method only configures the DAC to accept the
+            /// event; the event must be routed to the DAC through the
+            /// event system.
+            pub fn $set_start_event(&mut self, enabled: bool, inverted: bool) {
+                self.set_event_control($invei, inverted);
+                self.set_event_control($startei, enabled);
+            }
+
+            /// Generate an event when the data buffer of this channel is
+            /// empty, and when the result of its interpolation filter is
+            /// ready
+            pub fn $set_output_events(&mut self, empty: bool, result_ready: bool) {
+                self.set_event_control($emptyeo, empty);
+                self.set_event_control($resrdyeo, result_ready);
+            }
+
+            /// Write `buffer` to the data buffer of this channel using the
+            /// `channel` DMA channel, one sample each time the data buffer
+            /// empties.  Enable the start event to pace the conversions,
+            /// for example with the overflow event of a timer.  The
+            /// returned handle owns the DAC until the transfer is freed.
+            /// If the buffer is empty or longer than 65535 samples,
+            /// returns `Error::BufferLength` along with the untouched DAC
+            /// and channel.
+            pub fn $dma_write<CH: ChannelId>(
+                self,
+                mut channel: Channel<CH>,
+                buffer: &'static [u16],
+            ) -> Result<DacDmaWrite<Self, CH>, (Error, Self, Channel<CH>)> {
+                if buffer.is_empty() || buffer.len() > u16::MAX as usize {
+                    return Err((Error::BufferLength, self, channel));
+                }
+                let databuf = &self.dac.databuf[$num] as *const _ as u32;
+
+                channel.configure(TriggerSource::$TRIGGER, TriggerAction::Burst, Priority::Level0);
+                channel
+                    .descriptor()
+                    .configure(
+                        BeatSize::HalfWord,
+                        buffer.len() as u16,
+                        buffer.as_ptr() as u32,
+                        true,
+                        databuf,
+                        false,
+                    )
+                    .link(None);
+                channel.start();
+
+                Ok(DacDmaWrite {
+                    dac: self,
+                    channel,
+                    buffer,
+                })
+            }
+        }
+    };
+}
+
+dac_channel!(
+    0, VOUT1, Pa2<PfB>, VOUT1, DAC_EMPTY0,
+    (1 << 0, 1 << 4, 1 << 2, 1 << 6),
+    (1 << 2, 1 << 4),
+    write0, write_buffer0, set_start_event0, set_output_events0, dma_write0
+);
+dac_channel!(
+    1, VOUT0, VOUT0, Pa5<PfB>, DAC_EMPTY1,
+    (1 << 1, 1 << 5, 1 << 3, 1 << 7),
+    (1 << 3, 1 << 5),
+    write1, write_buffer1, set_start_event1, set_output_events1, dma_write1
+);
+
+impl<DAC, CH: ChannelId> DacDmaWrite<DAC, CH> {
+    /// Returns `Ok` once every sample has been written to the data buffer
+    pub fn poll(&mut self) -> nb::Result<(), dmac::Error> {
+        self.channel.wait()
+    }
+
+    /// Block until the transfer completes
+    pub fn wait(&mut self) -> Result<(), dmac::Error> {
+        nb::block!(self.poll())
+    }
+
+    /// Stop the transfer if it is still running, and release the DAC,
+    /// the DMA channel and the buffer
+    pub fn free(mut self) -> (DAC, Channel<CH>, &'static [u16]) {
+        self.channel.stop();
+        (self.dac, self.channel, self.buffer)
+    }
+}
@@ -1,8 +1,20 @@
+pub mod capture;
 pub mod clock;
+pub mod dac;
+pub mod device_id;
+pub mod dmac;
+pub mod eic;
+pub mod monotonic;
+pub mod nvm;
 pub mod pwm;
+pub mod rtc;
 pub mod sercom;
+pub mod smart_eeprom;
 pub mod timer;
 pub mod trng;
 
 #[cfg(feature="unproven")]
 pub mod adc;
+
+#[cfg(feature="unproven")]
+pub mod watchdog;
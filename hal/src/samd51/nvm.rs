@@ -0,0 +1,262 @@
+//! Working with the Non-Volatile Memory Controller (NVMCTRL).
+//!
+//! The flash is organized in pages, which are the unit of writing, and
+//! blocks of sixteen pages, which are the unit of erasing.  Erasing sets
+//! every bit of the block; writing can only clear bits, so a block must be
+//! erased before its pages are rewritten.  The flash is also divided into
+//! 32 lock regions that protect it against accidental erasing and writing.
+//!
+//! The flash is split into two banks.  The CPU stalls on accesses to a
+//! bank while the NVM controller is busy with it, but can keep executing
+//! from the other bank; a firmware update can be written to the inactive
+//! bank and started with `swap_banks_and_reset`.  The methods of `Nvm`
+//! block until each operation has completed.
+//!
+//! ```no_run
+//! let mut nvm = Nvm::new(peripherals.NVMCTRL);
+//! let address = nvm.inactive_bank_address();
+//! nvm.erase_block(address).unwrap();
+//! nvm.write(address, &firmware).unwrap();
+//! nvm.swap_banks_and_reset();
+//! ```
+use crate::target_device::nvmctrl::ctrlb;
+use crate::target_device::NVMCTRL;
+use core::ptr;
+
+/// The number of lock regions of the flash
+pub const LOCK_REGIONS: u8 = 32;
+
+/// The number of pages in a block
+pub const PAGES_PER_BLOCK: usize = 16;
+
+/// One of the two flash banks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bank {
+    A,
+    B,
+}
+
+/// Errors reported by the NVM controller
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The address is outside the flash, or not aligned to the unit of
+    /// the operation, or was rejected by the NVM controller
+    InvalidAddress,
+    /// The operation targets a locked region
+    Locked,
+    /// An invalid command or command key was used
+    Programming,
+    /// The NVM controller reported an error, such as a failure to
+    /// swap the banks
+    NvmError,
+}
+
+/// `Nvm` encapsulates the NVMCTRL hardware and provides erasing, writing
+/// and locking of the flash.
+pub struct Nvm {
+    pub(crate) nvm: NVMCTRL,
+}
+
+impl Nvm {
+    /// Take ownership of the NVMCTRL.  The flash is switched to manual
+    /// write mode, so that pages are only written by an explicit command.
+    pub fn new(nvm: NVMCTRL) -> Self {
+        nvm.ctrla.modify(|_, w| w.wmode().man());
+        Self { nvm }
+    }
+
+    /// Release the NVMCTRL
+    pub fn free(self) -> NVMCTRL {
+        self.nvm
+    }
+
+    /// Returns the size of a page in bytes
+    pub fn page_size(&self) -> usize {
+        8 << self.nvm.param.read().psz().bits()
+    }
+
+    /// Returns the number of pages in the flash
+    pub fn page_count(&self) -> usize {
+        self.nvm.param.read().nvmp().bits() as usize
+    }
+
+    /// Returns the size of a block in bytes
+    pub fn block_size(&self) -> usize {
+        self.page_size() * PAGES_PER_BLOCK
+    }
+
+    /// Returns the size of the flash in bytes
+    pub fn flash_size(&self) -> usize {
+        self.page_size() * self.page_count()
+    }
+
+    /// Returns the size of a lock region in bytes
+    pub fn lock_region_size(&self) -> usize {
+        self.flash_size() / LOCK_REGIONS as usize
+    }
+
+    /// Returns the lock region containing `address`
+    pub fn region_of(&self, address: u32) -> u8 {
+        (address as usize / self.lock_region_size()) as u8
+    }
+
+    /// Returns true if the lock region `region` is locked
+    pub fn is_region_locked(&self, region: u8) -> bool {
+        // A cleared bit indicates a locked region
+        self.nvm.runlock.read().runlock().bits() & (1 << region) == 0
+    }
+
+    /// Lock the lock region `region` until it is unlocked or the device
+    /// is reset.  The lock bits in the user row set the state at reset.
+    pub fn lock_region(&mut self, region: u8) -> Result<(), Error> {
+        let address = self.region_address(region)?;
+        self.command(address, |w| w.cmd().lr())
+    }
+
+    /// Unlock the lock region `region` until it is locked or the device
+    /// is reset
+    pub fn unlock_region(&mut self, region: u8) -> Result<(), Error> {
+        let address = self.region_address(region)?;
+        self.command(address, |w| w.cmd().ur())
+    }
+
+    /// Returns the bank that is mapped at address zero and executed from
+    pub fn active_bank(&self) -> Bank {
+        if self.nvm.status.read().afirst().bit_is_set() {
+            Bank::A
+        } else {
+            Bank::B
+        }
+    }
+
+    /// Returns the size of a bank in bytes
+    pub fn bank_size(&self) -> usize {
+        self.flash_size() / 2
+    }
+
+    /// Returns the address at which the inactive bank is mapped, which
+    /// is always the upper half of the flash
+    pub fn inactive_bank_address(&self) -> u32 {
+        self.bank_size() as u32
+    }
+
+    /// Swap the banks, so that the inactive bank is mapped at address
+    /// zero, and reset the device.  Returns only if the swap failed.
+    pub fn swap_banks_and_reset(&mut self) -> Error {
+        match self.command(0, |w| w.cmd().bkswrst()) {
+            // The controller resets the device once the swap is done
+            Ok(()) => loop {
+                cortex_m::asm::nop();
+            },
+            Err(e) => e,
+        }
+    }
+
+    /// Erase the block starting at `address`, which must be aligned to
+    /// the block size
+    pub fn erase_block(&mut self, address: u32) -> Result<(), Error> {
+        let block_size = self.block_size();
+        self.check_range(address, block_size, block_size)?;
+        self.command(address, |w| w.cmd().eb())
+    }
+
+    /// Write `data` starting at `address`, which must be word aligned.
+    /// The data may span several pages, which must have been erased.
+    pub fn write(&mut self, address: u32, data: &[u32]) -> Result<(), Error> {
+        self.check_range(address, data.len() * 4, 4)?;
+
+        let page_size = self.page_size() as u32;
+        let mut address = address;
+        let mut data = data;
+        while !data.is_empty() {
+            let page = address & !(page_size - 1);
+            let words = data.len().min(((page + page_size - address) / 4) as usize);
+
+            self.command(page, |w| w.cmd().pbc())?;
+            for (i, word) in data[..words].iter().enumerate() {
+                // The page buffer is written through the flash address space
+                unsafe { ptr::write_volatile((address as *mut u32).add(i), *word) };
+            }
+            self.command(page, |w| w.cmd().wp())?;
+
+            address += words as u32 * 4;
+            data = &data[words..];
+        }
+        Ok(())
+    }
+
+    /// Read `data.len()` words starting at `address`, which must be word
+    /// aligned
+    pub fn read(&self, address: u32, data: &mut [u32]) -> Result<(), Error> {
+        self.check_range(address, data.len() * 4, 4)?;
+        for (i, word) in data.iter_mut().enumerate() {
+            *word = unsafe { ptr::read_volatile((address as *const u32).add(i)) };
+        }
+        Ok(())
+    }
+
+    fn region_address(&self, region: u8) -> Result<u32, Error> {
+        if region >= LOCK_REGIONS {
+            return Err(Error::InvalidAddress);
+        }
+        Ok((region as usize * self.lock_region_size()) as u32)
+    }
+
+    /// Check that `len` bytes from `address` are within the flash and
+    /// unlocked, and that `address` is aligned to `align` bytes
+    fn check_range(&self, address: u32, len: usize, align: usize) -> Result<(), Error> {
+        let in_flash = match (address as usize).checked_add(len) {
+            Some(end) => end <= self.flash_size(),
+            None => false,
+        };
+        if address as usize % align != 0 || !in_flash {
+            return Err(Error::InvalidAddress);
+        }
+        if len == 0 {
+            return Ok(());
+        }
+        let first = self.region_of(address);
+        let last = self.region_of(address + len as u32 - 1);
+        if (first..=last).any(|region| self.is_region_locked(region)) {
+            return Err(Error::Locked);
+        }
+        Ok(())
+    }
+
+    /// Execute a command on `address` and wait for it to complete
+    pub(crate) fn command<F>(&mut self, address: u32, cmd: F) -> Result<(), Error>
+    where
+        F: FnOnce(&mut ctrlb::W) -> &mut ctrlb::W,
+    {
+        while self.nvm.status.read().ready().bit_is_clear() {}
+
+        // Clear the errors of any previous command
+        self.nvm.intflag.write(|w| {
+            w.done().set_bit();
+            w.addre().set_bit();
+            w.proge().set_bit();
+            w.locke().set_bit();
+            w.nvme().set_bit()
+        });
+        self.nvm.addr.write(|w| unsafe { w.addr().bits(address) });
+        self.nvm.ctrlb.write(|w| {
+            cmd(w);
+            w.cmdex().key()
+        });
+
+        while self.nvm.status.read().ready().bit_is_clear() {}
+
+        let flags = self.nvm.intflag.read();
+        if flags.addre().bit_is_set() {
+            Err(Error::InvalidAddress)
+        } else if flags.locke().bit_is_set() {
+            Err(Error::Locked)
+        } else if flags.proge().bit_is_set() {
+            Err(Error::Programming)
+        } else if flags.nvme().bit_is_set() {
+            Err(Error::NvmError)
+        } else {
+            Ok(())
+        }
+    }
+}
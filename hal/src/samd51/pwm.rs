@@ -1,16 +1,18 @@
-use crate::gpio::{Pa1, Pa5, Pa7, Pa9, Pa11, Pa13, Pa15, Pa17, Pa19, Pb31, PfE};
+use crate::gpio::{self, IntoFunction, Pa1, Pa5, Pa7, Pa9, Pa11, Pa13, Pa15, Pa17, Pa19, Pb31, PfE, Port};
 
 #[cfg(all(not(feature = "samd51g19a"), not(feature = "samd51g18a")))]
 use crate::gpio::{Pa23, Pa25, Pb9, Pb11, Pb13, Pb15};
 
 use crate::clock;
-use crate::timer::TimerParams;
+use crate::timer::{Error, TimerParams};
 use crate::time::Hertz;
 use crate::hal::PwmPin;
-use crate::target_device::{TC0, TC1, TC2, TC3, MCLK};
+#[cfg(feature = "unproven")]
+use crate::hal::Pwm;
+use crate::target_device::{TC0, TC1, TC2, TC3, TCC0, TCC1, TCC2, MCLK};
 
 #[cfg(all(not(feature = "samd51g19a"), not(feature = "samd51g18a")))]
-use crate::target_device::{TC4, TC5};
+use crate::target_device::{TC4, TC5, TCC3, TCC4};
 
 pub enum TC0Pinout {
     Pa5(Pa5<PfE>),
@@ -48,8 +50,17 @@ pub enum TC5Pinout {
     Pb15(Pb15<PfE>),
 }
 
-pub enum Channel { 
-    C0
+/// A compare channel.  The TC based PWMs only use `C0`; the TCC based
+/// PWMs support as many channels as the TCC instance has compare
+/// registers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Channel {
+    C0,
+    C1,
+    C2,
+    C3,
+    C4,
+    C5,
 }
 
 macro_rules! pwm {
@@ -111,17 +122,32 @@ impl $TYPE {
 
     pub fn get_period(&self) -> Hertz {
         let count = self.tc.count16();
-        let divisor = count.ctrla.read().prescaler().bits(); 
+        let divisor = DIVIDERS[count.ctrla.read().prescaler().bits() as usize];
         let top = count.cc[0].read().cc().bits();
-        Hertz(self.clock_freq.0 / divisor as u32 / (top + 1) as u32)
+        Hertz(self.clock_freq.0 / divisor / (top + 1) as u32)
     }
 
     pub fn set_period<P>(&mut self, period: P)
     where
         P: Into<Hertz>
     {
-        let period = period.into();
         let params = TimerParams::new(period, self.clock_freq.0);
+        self.write_period(params);
+    }
+
+    /// Change the period like `set_period`, but return an error instead
+    /// of panicking when `period` is out of range.  Returns the period
+    /// that is actually achieved.
+    pub fn try_set_period<P>(&mut self, period: P) -> Result<Hertz, Error>
+    where
+        P: Into<Hertz>
+    {
+        let params = TimerParams::try_new(period, self.clock_freq.0)?;
+        self.write_period(params);
+        Ok(self.get_period())
+    }
+
+    fn write_period(&mut self, params: TimerParams) {
         let count = self.tc.count16();
         count.ctrla.modify(|_, w| w.enable().clear_bit());
         count.ctrla.modify(|_, w| {
@@ -189,3 +215,636 @@ pwm! {
     Pwm4: (TC4, TC4Pinout, Tc4Tc5Clock, apbcmask, tc4_, Pwm4Wrapper),
     Pwm5: (TC5, TC5Pinout, Tc4Tc5Clock, apbcmask, tc5_, Pwm5Wrapper),
 }
+
+/// The TccOutputPin trait makes it more ergonomic to convert a pin into a
+/// TCC waveform output.  You should not implement this trait for
+/// yourself; only the implementations in the pwm module make sense.
+pub trait TccOutputPin<T> {
+    fn into_tcc_output(self, port: &mut Port) -> T;
+}
+
+/// Marks a waveform output of the `TCC` instance.  You should not
+/// implement this trait for yourself; only the implementations in the
+/// pwm module make sense.
+pub trait TccOutput<TCC> {}
+
+/// Marks a set of waveform outputs of the `TCC` instance, given as a
+/// tuple of outputs.  You should not implement this trait for yourself;
+/// only the implementations in the pwm module make sense.
+pub trait TccOutputs<TCC> {}
+
+/// The tcc_output macro defines the given waveform output and implements
+/// TccOutputPin for the given pins.  The TccOutputPin implementation will
+/// configure the pin for the appropriate function and return the pin
+/// wrapped in the output type.
+macro_rules! tcc_output {
+    ($TCC:ident, $OutputType:ident {
+        $($(#[$attr:meta])* $PinType:ident ($Pf:ident),)+
+    }
+    ) => {
+/// Represents a numbered waveform output of the associated TCC instance.
+/// With the default output matrix, output `n` is driven by compare
+/// channel `n` modulo the number of compare channels.
+pub struct $OutputType<PIN>(PIN);
+
+impl<PIN> $OutputType<PIN> {
+    /// Construct the output from the appropriate pin in any mode.
+    /// You may find it more convenient to use the `into_tcc_output` trait
+    /// and avoid referencing the output type.
+    pub fn new(pin: PIN) -> Self {
+        $OutputType(pin)
+    }
+
+    /// Release the pin
+    pub fn free(self) -> PIN {
+        self.0
+    }
+}
+
+impl<PIN> TccOutput<$TCC> for $OutputType<PIN> {}
+
+$(
+    $(#[$attr])*
+    impl<MODE> TccOutputPin<$OutputType<gpio::$PinType<gpio::$Pf>>> for gpio::$PinType<MODE> {
+        fn into_tcc_output(self, port: &mut Port) -> $OutputType<gpio::$PinType<gpio::$Pf>> {
+            $OutputType::new(self.into_function(port))
+        }
+    }
+)+
+    };
+}
+
+macro_rules! tcc_outputs {
+    ($($T:ident),+) => {
+        impl<TCC, $($T: TccOutput<TCC>),+> TccOutputs<TCC> for ($($T,)+) {}
+    };
+}
+
+tcc_outputs!(A);
+tcc_outputs!(A, B);
+tcc_outputs!(A, B, C);
+tcc_outputs!(A, B, C, D);
+tcc_outputs!(A, B, C, D, E);
+tcc_outputs!(A, B, C, D, E, F);
+tcc_outputs!(A, B, C, D, E, F, G);
+tcc_outputs!(A, B, C, D, E, F, G, H);
+
+tcc_output!(TCC0, Tcc0Wo0 {
+    Pa8(PfF),
+    Pa20(PfG),
+    Pb12(PfG),
+});
+tcc_output!(TCC0, Tcc0Wo1 {
+    Pa9(PfF),
+    Pa21(PfG),
+    Pb13(PfG),
+});
+tcc_output!(TCC0, Tcc0Wo2 {
+    Pa10(PfF),
+    Pa22(PfG),
+    Pb14(PfG),
+});
+tcc_output!(TCC0, Tcc0Wo3 {
+    Pa11(PfF),
+    Pa23(PfG),
+    Pb15(PfG),
+});
+tcc_output!(TCC0, Tcc0Wo4 {
+    Pa16(PfG),
+    Pb10(PfF),
+    Pb16(PfG),
+});
+tcc_output!(TCC0, Tcc0Wo5 {
+    Pa17(PfG),
+    Pb11(PfF),
+    Pb17(PfG),
+});
+tcc_output!(TCC0, Tcc0Wo6 {
+    Pa12(PfF),
+    Pa18(PfG),
+    Pb30(PfG),
+});
+tcc_output!(TCC0, Tcc0Wo7 {
+    Pa13(PfF),
+    Pa19(PfG),
+    Pb31(PfG),
+});
+tcc_output!(TCC1, Tcc1Wo0 {
+    Pa16(PfF),
+    Pb10(PfG),
+});
+tcc_output!(TCC1, Tcc1Wo1 {
+    Pa17(PfF),
+    Pb11(PfG),
+});
+tcc_output!(TCC1, Tcc1Wo2 {
+    Pa12(PfG),
+    Pa14(PfG),
+    Pa18(PfF),
+});
+tcc_output!(TCC1, Tcc1Wo3 {
+    Pa13(PfG),
+    Pa15(PfG),
+    Pa19(PfF),
+});
+tcc_output!(TCC1, Tcc1Wo4 {
+    Pa8(PfG),
+    Pa20(PfF),
+});
+tcc_output!(TCC1, Tcc1Wo5 {
+    Pa9(PfG),
+    Pa21(PfF),
+});
+tcc_output!(TCC1, Tcc1Wo6 {
+    Pa10(PfG),
+    Pa22(PfF),
+});
+tcc_output!(TCC1, Tcc1Wo7 {
+    Pa11(PfG),
+    Pa23(PfF),
+});
+tcc_output!(TCC2, Tcc2Wo0 {
+    Pa14(PfF),
+    Pa30(PfF),
+});
+tcc_output!(TCC2, Tcc2Wo1 {
+    Pa15(PfF),
+    Pa31(PfF),
+});
+tcc_output!(TCC2, Tcc2Wo2 {
+    Pa24(PfF),
+    Pb2(PfF),
+});
+#[cfg(all(not(feature = "samd51g19a"), not(feature = "samd51g18a")))]
+tcc_output!(TCC3, Tcc3Wo0 {
+    Pb12(PfF),
+    Pb16(PfF),
+});
+#[cfg(all(not(feature = "samd51g19a"), not(feature = "samd51g18a")))]
+tcc_output!(TCC3, Tcc3Wo1 {
+    Pb13(PfF),
+    Pb17(PfF),
+});
+#[cfg(all(not(feature = "samd51g19a"), not(feature = "samd51g18a")))]
+tcc_output!(TCC4, Tcc4Wo0 {
+    Pb14(PfF),
+    Pb30(PfF),
+});
+#[cfg(all(not(feature = "samd51g19a"), not(feature = "samd51g18a")))]
+tcc_output!(TCC4, Tcc4Wo1 {
+    Pb15(PfF),
+    Pb31(PfF),
+});
+
+/// The waveform generated by a TCC based PWM
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Waveform {
+    /// Single-slope PWM; the counter counts up to the period and wraps
+    SingleSlope,
+    /// Dual-slope PWM, updated when the counter reaches the top
+    DualSlopeTop,
+    /// Dual-slope PWM, updated when the counter reaches zero
+    DualSlopeBottom,
+    /// Dual-slope PWM, updated when the counter reaches the top or zero
+    DualSlopeBoth,
+    /// Dual-slope PWM, with the compare value of channel 0 used for the
+    /// up count and that of channel 1 for the down count
+    DualSlopeCritical,
+}
+
+impl Waveform {
+    fn is_dual_slope(self) -> bool {
+        self != Waveform::SingleSlope
+    }
+}
+
+/// A recoverable fault input, fed by the match or capture event of
+/// channel 0 (`A`) or channel 1 (`B`)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FaultInput {
+    A,
+    B,
+}
+
+/// How the counter is halted while a recoverable fault is active
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FaultHalt {
+    /// The counter is not halted
+    Disabled,
+    /// The counter is halted until the fault is gone
+    Hardware,
+    /// The counter is halted until the fault is gone and it has been
+    /// cleared with `clear_fault`
+    Software,
+    /// The fault is treated as a non-recoverable fault, which drives
+    /// the outputs to their fault state
+    NonRecoverable,
+}
+
+/// The configuration of a recoverable fault input
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FaultConfig {
+    /// The fault is active when the event is low, rather than high
+    pub inverted: bool,
+    /// Only consider the fault while the output of `channel` is active
+    pub qualified: bool,
+    /// Keep the outputs inactive until the end of the period once the
+    /// fault is gone
+    pub keep: bool,
+    /// Restart the counter when the fault occurs
+    pub restart: bool,
+    /// How the counter is halted while the fault is active
+    pub halt: FaultHalt,
+    /// The channel whose output qualifies the fault, one of `C0` to `C3`
+    pub channel: Channel,
+    /// The number of samples of the fault input filter, 0 to disable
+    pub filter: u8,
+}
+
+/// The dividers of the TC and TCC prescalers, indexed by the PRESCALER
+/// field
+const DIVIDERS: [u32; 8] = [1, 2, 4, 8, 16, 64, 256, 1024];
+
+/// Returns the prescaler setting and the number of prescaled cycles in a
+/// period of `freq`, for a counter of at most `max` cycles
+fn tcc_params(freq: Hertz, src_freq: u32, max: u32) -> Result<(u8, u32), Error> {
+    let ticks = src_freq / freq.0.max(1);
+    if ticks == 0 {
+        return Err(Error::FrequencyTooHigh {
+            max: Hertz(src_freq),
+        });
+    }
+    for (prescaler, divider) in DIVIDERS.iter().enumerate() {
+        let cycles = ticks / divider;
+        if cycles <= max {
+            return Ok((prescaler as u8, cycles.max(1)));
+        }
+    }
+    let slowest = max as u64 * 1024;
+    Err(Error::FrequencyTooLow {
+        min: Hertz(((src_freq as u64 + slowest - 1) / slowest) as u32),
+    })
+}
+
+macro_rules! pwm_tcc {
+    ($($TYPE:ident: ($TCC:ident, $clock:ident, $apmask:ident, $apbits:ident, $channels:expr, $max:expr),)+) => {
+        $(
+
+/// A multi-channel PWM driven by a TCC instance.  The waveform outputs,
+/// given as a tuple, are held while the PWM is in use.
+pub struct $TYPE<OUTPUTS> {
+    /// The frequency of the attached clock, not the period of the pwm.
+    /// Used to calculate the period of the pwm.
+    clock_freq: Hertz,
+    tcc: $TCC,
+    outputs: OUTPUTS,
+    waveform: Waveform,
+    duty: [u32; $channels],
+    enabled: u8,
+}
+
+impl<OUTPUTS: TccOutputs<$TCC>> $TYPE<OUTPUTS> {
+    /// Configure the TCC for single-slope PWM with a period of `freq`,
+    /// with every channel enabled and a duty cycle of zero
+    pub fn new<F: Into<Hertz>> (
+        clock: &clock::$clock,
+        freq: F,
+        tcc: $TCC,
+        outputs: OUTPUTS,
+        mclk: &mut MCLK,
+    ) -> Self {
+        mclk.$apmask.modify(|_, w| w.$apbits().set_bit());
+        tcc.ctrla.write(|w| w.swrst().set_bit());
+        while tcc.syncbusy.read().swrst().bit_is_set() {}
+
+        let mut pwm = Self {
+            clock_freq: clock.freq(),
+            tcc,
+            outputs,
+            waveform: Waveform::SingleSlope,
+            duty: [0; $channels],
+            enabled: (1 << $channels) - 1,
+        };
+        pwm.tcc.wave.write(|w| w.wavegen().npwm());
+        pwm.sync();
+        pwm.set_period_cycles(freq.into());
+        pwm.tcc.ctrla.modify(|_, w| w.enable().set_bit());
+        pwm.sync();
+        pwm
+    }
+}
+
+impl<OUTPUTS> $TYPE<OUTPUTS> {
+    fn sync(&self) {
+        while self.tcc.syncbusy.read().bits() != 0 {}
+    }
+
+    /// Run `f` with the TCC disabled, as required to change the
+    /// enable-protected configuration
+    fn disabled<F: FnOnce(&$TCC)>(&mut self, f: F) {
+        self.tcc.ctrla.modify(|_, w| w.enable().clear_bit());
+        self.sync();
+        f(&self.tcc);
+        self.tcc.ctrla.modify(|_, w| w.enable().set_bit());
+        self.sync();
+    }
+
+    fn set_period_cycles(&mut self, freq: Hertz) {
+        if let Err(err) = self.try_set_period_cycles(freq) {
+            panic!("the period {}Hz is out of range for the TCC: {:?}", freq.0, err);
+        }
+    }
+
+    fn try_set_period_cycles(&mut self, freq: Hertz) -> Result<(), Error> {
+        let (prescaler, cycles) = tcc_params(freq, self.clock_freq.0, $max)?;
+        // A dual-slope period counts up to PER and back down
+        let per = if self.waveform.is_dual_slope() {
+            cycles / 2
+        } else {
+            cycles - 1
+        };
+        self.disabled(|tcc| {
+            tcc.ctrla.modify(|_, w| w.prescaler().bits(prescaler));
+            tcc.per().write(|w| unsafe { w.per().bits(per) });
+        });
+        // Keep the duty cycles within the new period
+        for channel in 0..$channels {
+            self.write_cc(channel);
+        }
+        Ok(())
+    }
+
+    fn write_cc(&mut self, channel: usize) {
+        let value = if self.enabled & (1 << channel) != 0 {
+            self.duty[channel]
+        } else {
+            0
+        };
+        self.sync();
+        self.tcc.ccbuf()[channel].write(|w| unsafe { w.ccbuf().bits(value) });
+    }
+
+    #[cfg(feature = "unproven")]
+    fn channel_index(channel: Channel) -> usize {
+        let index = channel as usize;
+        assert!(index < $channels, "the TCC does not have this channel");
+        index
+    }
+
+    /// Select the waveform.  The period is kept, but the duty cycles
+    /// must be scaled to the new maximum duty cycle.
+    pub fn set_waveform(&mut self, waveform: Waveform) {
+        let period = self.period();
+        self.waveform = waveform;
+        self.disabled(|tcc| {
+            tcc.wave.modify(|_, w| match waveform {
+                Waveform::SingleSlope => w.wavegen().npwm(),
+                Waveform::DualSlopeTop => w.wavegen().dstop(),
+                Waveform::DualSlopeBottom => w.wavegen().dsbottom(),
+                Waveform::DualSlopeBoth => w.wavegen().dsboth(),
+                Waveform::DualSlopeCritical => w.wavegen().dscritical(),
+            })
+        });
+        self.set_period_cycles(period);
+    }
+
+    fn period(&self) -> Hertz {
+        let divider = DIVIDERS[self.tcc.ctrla.read().prescaler().bits() as usize];
+        let per = self.tcc.per().read().per().bits();
+        let cycles = if self.waveform.is_dual_slope() {
+            per * 2
+        } else {
+            per + 1
+        };
+        Hertz(self.clock_freq.0 / divider / cycles.max(1))
+    }
+
+    /// Change the period, returning an error with the achievable range
+    /// instead of panicking when `period` is out of range.  Returns the
+    /// period that is actually achieved.
+    pub fn try_set_period<P: Into<Hertz>>(&mut self, period: P) -> Result<Hertz, Error> {
+        self.try_set_period_cycles(period.into())?;
+        Ok(self.period())
+    }
+
+    /// Invert the waveform output `output`
+    pub fn set_output_inverted(&mut self, output: u8, inverted: bool) {
+        let mask = 1 << (16 + output);
+        self.disabled(|tcc| {
+            tcc.drvctrl.modify(|r, w| unsafe {
+                if inverted {
+                    w.bits(r.bits() | mask)
+                } else {
+                    w.bits(r.bits() & !mask)
+                }
+            })
+        });
+    }
+
+    /// Configure the recoverable fault `input`, or disable it with
+    /// `None`.  The fault is fed by the event input of channel 0 for
+    /// `FaultInput::A`, or channel 1 for `FaultInput::B`, which must be
+    /// routed through the event system.
+    pub fn configure_fault(&mut self, input: FaultInput, config: Option<FaultConfig>) {
+        // FCTRLA and FCTRLB share the same layout
+        let bits = config.map_or(0, |config| {
+            let halt = match config.halt {
+                FaultHalt::Disabled => 0,
+                FaultHalt::Hardware => 1,
+                FaultHalt::Software => 2,
+                FaultHalt::NonRecoverable => 3,
+            };
+            (if config.inverted { 2 } else { 1 })
+                | (config.keep as u32) << 3
+                | (config.qualified as u32) << 4
+                | (config.restart as u32) << 7
+                | halt << 8
+                | (config.channel as u32) << 10
+                | ((config.filter & 0xf) as u32) << 24
+        });
+        let enabled = config.is_some();
+        self.disabled(|tcc| match input {
+            FaultInput::A => {
+                tcc.fctrla.write(|w| unsafe { w.bits(bits) });
+                tcc.evctrl.modify(|_, w| w.mcei0().bit(enabled));
+            }
+            FaultInput::B => {
+                tcc.fctrlb.write(|w| unsafe { w.bits(bits) });
+                tcc.evctrl.modify(|_, w| w.mcei1().bit(enabled));
+            }
+        });
+    }
+
+    /// Returns true while the recoverable fault `input` is active, or has
+    /// halted the counter until cleared
+    pub fn is_fault(&self, input: FaultInput) -> bool {
+        let status = self.tcc.status.read();
+        match input {
+            FaultInput::A => status.faulta().bit_is_set(),
+            FaultInput::B => status.faultb().bit_is_set(),
+        }
+    }
+
+    /// Clear the recoverable fault `input`, resuming a counter halted in
+    /// `FaultHalt::Software` once the fault is gone
+    pub fn clear_fault(&mut self, input: FaultInput) {
+        // Writing a 1 clears the flag
+        self.tcc.status.write(|w| match input {
+            FaultInput::A => w.faulta().set_bit(),
+            FaultInput::B => w.faultb().set_bit(),
+        });
+    }
+
+    /// Set the level the waveform output `output` is driven to while a
+    /// non-recoverable fault is active, or leave it running with `None`
+    pub fn set_fault_state(&mut self, output: u8, state: Option<bool>) {
+        let enable = 1 << output;
+        let value = 1 << (8 + output);
+        self.disabled(|tcc| {
+            tcc.drvctrl.modify(|r, w| unsafe {
+                let bits = r.bits() & !(enable | value);
+                match state {
+                    Some(true) => w.bits(bits | enable | value),
+                    Some(false) => w.bits(bits | enable),
+                    None => w.bits(bits),
+                }
+            })
+        });
+    }
+
+    /// Disable the TCC and release it along with the waveform outputs
+    pub fn free(self) -> ($TCC, OUTPUTS) {
+        self.tcc.ctrla.write(|w| w.swrst().set_bit());
+        while self.tcc.syncbusy.read().swrst().bit_is_set() {}
+        (self.tcc, self.outputs)
+    }
+}
+
+#[cfg(feature = "unproven")]
+impl<OUTPUTS> Pwm for $TYPE<OUTPUTS> {
+    type Channel = Channel;
+    type Time = Hertz;
+    type Duty = u32;
+
+    fn disable(&mut self, channel: Channel) {
+        let index = Self::channel_index(channel);
+        self.enabled &= !(1 << index);
+        self.write_cc(index);
+    }
+
+    fn enable(&mut self, channel: Channel) {
+        let index = Self::channel_index(channel);
+        self.enabled |= 1 << index;
+        self.write_cc(index);
+    }
+
+    fn get_period(&self) -> Hertz {
+        self.period()
+    }
+
+    fn get_duty(&self, channel: Channel) -> u32 {
+        self.duty[Self::channel_index(channel)]
+    }
+
+    fn get_max_duty(&self) -> u32 {
+        self.tcc.per().read().per().bits()
+    }
+
+    fn set_duty(&mut self, channel: Channel, duty: u32) {
+        let index = Self::channel_index(channel);
+        self.duty[index] = duty;
+        self.write_cc(index);
+    }
+
+    fn set_period<P>(&mut self, period: P)
+    where
+        P: Into<Hertz>,
+    {
+        self.set_period_cycles(period.into());
+    }
+}
+
+)+}}
+
+macro_rules! pwm_tcc_dead_time {
+    ($($TYPE:ident,)+) => {
+        $(
+
+/// Dead-time insertion is only available on TCC0 and TCC1
+impl<OUTPUTS> $TYPE<OUTPUTS> {
+    /// Set the number of clock cycles by which the rising edge of the low
+    /// side output and of the high side output are delayed
+    pub fn set_dead_time(&mut self, low_side: u8, high_side: u8) {
+        self.disabled(|tcc| {
+            tcc.wexctrl.modify(|_, w| unsafe {
+                w.dtls().bits(low_side);
+                w.dths().bits(high_side)
+            })
+        });
+    }
+
+    /// Insert dead time into the output of `channel`, which drives the
+    /// complementary outputs WO[n] and WO[n + 4].  Only channels `C0` to
+    /// `C3` support dead-time insertion.
+    pub fn enable_dead_time(&mut self, channel: Channel) {
+        assert!((channel as u32) < 4, "dead-time insertion is only supported on C0 to C3");
+        let mask = 1 << (8 + channel as u32);
+        self.disabled(|tcc| tcc.wexctrl.modify(|r, w| unsafe { w.bits(r.bits() | mask) }));
+    }
+
+    /// Stop inserting dead time into the output of `channel`
+    pub fn disable_dead_time(&mut self, channel: Channel) {
+        let mask = 1 << (8 + channel as u32);
+        self.disabled(|tcc| tcc.wexctrl.modify(|r, w| unsafe { w.bits(r.bits() & !mask) }));
+    }
+}
+
+)+}}
+
+macro_rules! pwm_tcc_pattern {
+    ($($TYPE:ident,)+) => {
+        $(
+
+/// The pattern generator is only available on TCC0 and TCC1
+impl<OUTPUTS> $TYPE<OUTPUTS> {
+    /// Override the waveform outputs with the pattern generator.  The
+    /// outputs whose bit is set in `enable` are driven to the matching
+    /// bit of `value`.  The pattern takes effect at the start of the next
+    /// period.
+    pub fn set_pattern(&mut self, enable: u8, value: u8) {
+        self.sync();
+        self.tcc
+            .pattbuf
+            .write(|w| unsafe { w.bits(enable as u16 | (value as u16) << 8) });
+    }
+
+    /// Stop overriding the waveform outputs at the start of the next
+    /// period
+    pub fn clear_pattern(&mut self) {
+        self.set_pattern(0, 0);
+    }
+}
+
+)+}}
+
+pwm_tcc! {
+    Tcc0Pwm: (TCC0, Tcc0Tcc1Clock, apbbmask, tcc0_, 6, 0xff_ffff),
+    Tcc1Pwm: (TCC1, Tcc0Tcc1Clock, apbbmask, tcc1_, 4, 0xff_ffff),
+    Tcc2Pwm: (TCC2, Tcc2Tcc3Clock, apbcmask, tcc2_, 3, 0xffff),
+}
+
+#[cfg(all(not(feature = "samd51g19a"), not(feature = "samd51g18a")))]
+pwm_tcc! {
+    Tcc3Pwm: (TCC3, Tcc2Tcc3Clock, apbcmask, tcc3_, 2, 0xffff),
+    Tcc4Pwm: (TCC4, Tcc4Clock, apbdmask, tcc4_, 2, 0xffff),
+}
+
+pwm_tcc_dead_time! {
+    Tcc0Pwm,
+    Tcc1Pwm,
+}
+
+pwm_tcc_pattern! {
+    Tcc0Pwm,
+    Tcc1Pwm,
+}
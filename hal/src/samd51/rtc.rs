@@ -0,0 +1,490 @@
+//! Working with the Real-Time Counter (RTC).
+//!
+//! The RTC keeps running in standby, making it suitable for keeping
+//! timestamps and waking the device periodically.  It can be operated
+//! either as a 32-bit counter (`Count32Mode`) that implements the
+//! `CountDown` and `Periodic` embedded_hal timer traits, or as a
+//! calendar (`ClockMode`) that counts seconds through to years.
+//!
+//! The RTC is clocked from OSC32KCTRL rather than a generic clock
+//! generator; `GenericClockController` selects the 1.024kHz output of
+//! either the ultra low power or the external 32kHz oscillator.  The
+//! RTC also provides eight backup registers that are retained in the
+//! backup sleep mode.  `resume_clock_mode` and `resume_count32_mode` take
+//! over a running RTC without resetting it, keeping the time and the
+//! backup registers across a reset.
+//!
+//! ```no_run
+//! let mut rtc = Rtc::clock_mode(peripherals.RTC, 1024.hz(), &mut peripherals.MCLK);
+//! rtc.set_time(Datetime {
+//!     seconds: 0,
+//!     minutes: 30,
+//!     hours: 12,
+//!     day: 1,
+//!     month: 1,
+//!     year: 20,
+//! });
+//! ```
+use crate::target_device::rtc::{MODE0, MODE2};
+use crate::target_device::{MCLK, RTC};
+use crate::time::{Hertz, Milliseconds};
+use core::marker::PhantomData;
+use hal::timer::{CountDown, Periodic};
+use nb;
+use void::Void;
+
+/// The calendar prescaler setting that divides `freq` down to 1Hz, if
+/// there is one
+fn clock_prescaler(freq: Hertz) -> Option<u8> {
+    if freq.0.is_power_of_two() && freq.0 <= 1024 {
+        // OFF is 0, DIV1 is 1, DIV2 is 2, ..., DIV1024 is 11
+        Some(freq.0.trailing_zeros() as u8 + 1)
+    } else {
+        None
+    }
+}
+
+/// Errors starting a count down
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error {
+    /// The timeout needs more than 2^32 counts of the RTC clock; `max`
+    /// is the longest timeout that can be counted
+    TimeoutTooLong { max: Milliseconds },
+}
+
+/// Type-level marker for the RTC operating as a 32-bit counter
+pub enum Count32Mode {}
+
+/// Type-level marker for the RTC operating as a calendar
+pub enum ClockMode {}
+
+/// A calendar date and time, as counted by the RTC in `ClockMode`.
+/// The hours are counted in 24 hour format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Datetime {
+    /// 0-59
+    pub seconds: u8,
+    /// 0-59
+    pub minutes: u8,
+    /// 0-23
+    pub hours: u8,
+    /// 1-31
+    pub day: u8,
+    /// 1-12
+    pub month: u8,
+    /// Years since 2000, 0-63
+    pub year: u8,
+}
+
+/// Selects which fields of the alarm must match the current time for
+/// the alarm to trigger.  Fields that are not compared are "don't care",
+/// so `HoursMinutesSeconds` triggers once a day.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AlarmMask {
+    /// The alarm is disabled
+    Off = 0,
+    /// Match seconds only
+    Seconds = 1,
+    /// Match minutes and seconds
+    MinutesSeconds = 2,
+    /// Match hours, minutes and seconds
+    HoursMinutesSeconds = 3,
+    /// Match day, hours, minutes and seconds
+    DayHoursMinutesSeconds = 4,
+    /// Match month, day, hours, minutes and seconds
+    MonthDayHoursMinutesSeconds = 5,
+    /// Match every field
+    YearMonthDayHoursMinutesSeconds = 6,
+}
+
+/// `Rtc` encapsulates the RTC hardware in one of its operating modes.
+/// The mode can be changed at runtime with the `into_count32_mode` and
+/// `into_clock_mode` methods.
+pub struct Rtc<MODE> {
+    rtc: RTC,
+    rtc_clock_freq: Hertz,
+    _mode: PhantomData<MODE>,
+}
+
+impl<MODE> Rtc<MODE> {
+    fn mode0(&self) -> &MODE0 {
+        self.rtc.mode0()
+    }
+
+    fn mode2(&self) -> &MODE2 {
+        self.rtc.mode2()
+    }
+
+    fn sync(&self) {
+        while self.mode0().syncbusy.read().bits() != 0 {}
+    }
+
+    fn enable(&mut self, enabled: bool) {
+        self.mode0().ctrla.modify(|_, w| w.enable().bit(enabled));
+        self.sync();
+    }
+
+    /// Power up the RTC bus clock and take over the RTC as it is
+    fn attach(rtc: RTC, rtc_clock_freq: Hertz, mclk: &mut MCLK) -> Self {
+        mclk.apbamask.modify(|_, w| w.rtc_().set_bit());
+
+        Rtc {
+            rtc,
+            rtc_clock_freq,
+            _mode: PhantomData,
+        }
+    }
+
+    fn reset(self) -> Self {
+        self.mode0().ctrla.modify(|_, w| w.enable().clear_bit());
+        self.sync();
+        self.mode0().ctrla.write(|w| w.swrst().set_bit());
+        self.sync();
+        self
+    }
+
+    fn into_mode<N>(self) -> Rtc<N> {
+        Rtc {
+            rtc: self.rtc,
+            rtc_clock_freq: self.rtc_clock_freq,
+            _mode: PhantomData,
+        }
+    }
+
+    /// Reconfigure the RTC as a 32-bit counter, counting at the
+    /// frequency of the RTC clock.  The counter is reset to zero.
+    pub fn into_count32_mode(mut self) -> Rtc<Count32Mode> {
+        self.enable(false);
+        self.mode0().ctrla.write(|w| {
+            w.mode().count32();
+            w.prescaler().div1();
+            // Synchronize COUNT continuously so that it can be read
+            w.countsync().set_bit()
+        });
+        self.mode0().count.write(|w| unsafe { w.bits(0) });
+        self.sync();
+        let mut rtc = self.into_mode();
+        rtc.enable(true);
+        rtc
+    }
+
+    /// Reconfigure the RTC as a calendar.  The prescaler is chosen so
+    /// that the calendar counts in seconds, which requires the RTC clock
+    /// frequency to be a power of two no greater than 1024Hz.
+    pub fn into_clock_mode(mut self) -> Rtc<ClockMode> {
+        let prescaler = clock_prescaler(self.rtc_clock_freq)
+            .expect("RTC clock must be a power of two no greater than 1024Hz");
+
+        self.enable(false);
+        self.mode2().ctrla.write(|w| unsafe {
+            w.mode().clock();
+            // Count hours in 24 hour format
+            w.clkrep().clear_bit();
+            w.prescaler().bits(prescaler);
+            // Synchronize CLOCK continuously so that it can be read
+            w.clocksync().set_bit()
+        });
+        self.sync();
+        let mut rtc = self.into_mode();
+        rtc.enable(true);
+        rtc
+    }
+
+    /// Enable the interrupt generation each time the periodic interval
+    /// `n` elapses, that is at the RTC clock frequency divided by
+    /// `2^(n + 3)`.  This method only configures the RTC to raise the
+    /// interrupt; it does not configure the interrupt controller or
+    /// define an interrupt handler.
+    pub fn enable_periodic_interrupt(&mut self, n: u8) {
+        assert!(n < 8, "periodic interval must be 0-7");
+        self.mode0().intenset.write(|w| unsafe { w.bits(1 << n) });
+    }
+
+    /// Disables the interrupt for the periodic interval `n`
+    pub fn disable_periodic_interrupt(&mut self, n: u8) {
+        assert!(n < 8, "periodic interval must be 0-7");
+        self.mode0().intenclr.write(|w| unsafe { w.bits(1 << n) });
+    }
+
+    /// Returns true if the periodic interval `n` has elapsed since the
+    /// flag was last cleared
+    pub fn is_periodic_interrupt(&self, n: u8) -> bool {
+        assert!(n < 8, "periodic interval must be 0-7");
+        self.mode0().intflag.read().bits() & (1 << n) != 0
+    }
+
+    /// Clear the flag of the periodic interval `n`
+    pub fn clear_periodic_interrupt(&mut self, n: u8) {
+        assert!(n < 8, "periodic interval must be 0-7");
+        // Writing a 1 clears only the flag of this interval
+        self.mode0().intflag.write(|w| unsafe { w.bits(1 << n) });
+    }
+
+    /// Read the backup register `n`, 0-7.  The backup registers keep
+    /// their contents in the backup sleep mode, and across resets other
+    /// than power-on or a software reset of the RTC.
+    pub fn backup_register(&self, n: usize) -> u32 {
+        self.mode0().bkup[n].read().bits()
+    }
+
+    /// Write the backup register `n`, 0-7
+    pub fn set_backup_register(&mut self, n: usize, value: u32) {
+        self.mode0().bkup[n].write(|w| unsafe { w.bits(value) });
+    }
+
+    /// Returns the frequency of the RTC clock
+    pub fn rtc_clock_freq(&self) -> Hertz {
+        self.rtc_clock_freq
+    }
+
+    /// Disable the RTC and release the peripheral
+    pub fn free(mut self) -> RTC {
+        self.enable(false);
+        self.rtc
+    }
+}
+
+impl Rtc<Count32Mode> {
+    /// Configure the RTC as a 32-bit counter counting at
+    /// `rtc_clock_freq`, which must match the clock selected in
+    /// OSC32KCTRL.
+    pub fn count32_mode(rtc: RTC, rtc_clock_freq: Hertz, mclk: &mut MCLK) -> Self {
+        Rtc::<Count32Mode>::attach(rtc, rtc_clock_freq, mclk).reset().into_count32_mode()
+    }
+
+    /// Take over an RTC that already runs as a 32-bit counter, such as
+    /// after a reset or a wake up from backup sleep, keeping its count,
+    /// its configuration and the backup registers.  An RTC that is
+    /// disabled or in another mode is configured as by `count32_mode`.
+    pub fn resume_count32_mode(rtc: RTC, rtc_clock_freq: Hertz, mclk: &mut MCLK) -> Self {
+        let rtc = Rtc::<Count32Mode>::attach(rtc, rtc_clock_freq, mclk);
+        let ctrl = rtc.mode0().ctrla.read();
+        if ctrl.enable().bit_is_set() && ctrl.mode().is_count32() && ctrl.prescaler().is_div1() {
+            // Synchronize COUNT continuously so that it can be read
+            rtc.mode0().ctrla.modify(|_, w| w.countsync().set_bit());
+            rtc.sync();
+            rtc
+        } else {
+            rtc.reset().into_count32_mode()
+        }
+    }
+
+    /// Returns the current counter value
+    pub fn count32(&self) -> u32 {
+        self.sync();
+        self.mode0().count.read().bits()
+    }
+
+    /// Set the counter value
+    pub fn set_count32(&mut self, count: u32) {
+        self.mode0().count.write(|w| unsafe { w.bits(count) });
+        self.sync();
+    }
+
+    /// Set the compare value.  When `clear_on_match` is true, the
+    /// counter restarts from zero after it reaches the compare value.
+    pub fn set_compare(&mut self, compare: u32, clear_on_match: bool) {
+        self.enable(false);
+        self.mode0()
+            .ctrla
+            .modify(|_, w| w.matchclr().bit(clear_on_match));
+        self.mode0().comp[0].write(|w| unsafe { w.bits(compare) });
+        self.sync();
+        self.enable(true);
+    }
+
+    /// Enable the interrupt generation when the counter matches the
+    /// compare value.  This method only configures the RTC to raise the
+    /// interrupt; it does not configure the interrupt controller or
+    /// define an interrupt handler.
+    pub fn enable_compare_interrupt(&mut self) {
+        self.mode0().intenset.write(|w| w.cmp0().set_bit());
+    }
+
+    /// Disables the compare interrupt
+    pub fn disable_compare_interrupt(&mut self) {
+        self.mode0().intenclr.write(|w| w.cmp0().set_bit());
+    }
+
+    /// Returns true if the counter has matched the compare value since
+    /// the flag was last cleared
+    pub fn is_compare_interrupt(&self) -> bool {
+        self.mode0().intflag.read().cmp0().bit_is_set()
+    }
+
+    /// Clear the compare flag
+    pub fn clear_compare_interrupt(&mut self) {
+        // Writing a 1 clears the flag
+        self.mode0().intflag.write(|w| w.cmp0().set_bit());
+    }
+
+    /// Enable the interrupt generation when the counter overflows
+    pub fn enable_overflow_interrupt(&mut self) {
+        self.mode0().intenset.write(|w| w.ovf().set_bit());
+    }
+
+    /// Disables the overflow interrupt
+    pub fn disable_overflow_interrupt(&mut self) {
+        self.mode0().intenclr.write(|w| w.ovf().set_bit());
+    }
+
+    /// Returns true if the counter has overflowed since the flag was
+    /// last cleared
+    pub fn is_overflow_interrupt(&self) -> bool {
+        self.mode0().intflag.read().ovf().bit_is_set()
+    }
+
+    /// Clear the overflow flag
+    pub fn clear_overflow_interrupt(&mut self) {
+        self.mode0().intflag.write(|w| w.ovf().set_bit());
+    }
+
+    /// Start the count down like `CountDown::start`, but return an error
+    /// instead of panicking when `timeout` is longer than 2^32 counts of
+    /// the RTC clock, which is about 36 hours at 32.768kHz
+    pub fn try_start<T>(&mut self, timeout: T) -> Result<(), Error>
+    where
+        T: Into<Milliseconds>,
+    {
+        let ms: Milliseconds = timeout.into();
+        let freq = self.rtc_clock_freq.0.max(1) as u64;
+        let cycles = (ms.0 as u64 * freq / 1_000).max(1);
+        if cycles > 1 << 32 {
+            let max = ((1u64 << 32) * 1_000 / freq).min(u32::MAX as u64);
+            return Err(Error::TimeoutTooLong {
+                max: Milliseconds(max as u32),
+            });
+        }
+
+        // The counter restarts after reaching the compare value, so a
+        // period of `cycles` counts needs a compare value one less.
+        self.set_compare((cycles - 1) as u32, true);
+        self.set_count32(0);
+        self.clear_compare_interrupt();
+        Ok(())
+    }
+}
+
+impl Periodic for Rtc<Count32Mode> {}
+impl CountDown for Rtc<Count32Mode> {
+    type Time = Milliseconds;
+
+    /// Panics if `timeout` is longer than 2^32 counts of the RTC clock;
+    /// see `try_start`
+    fn start<T>(&mut self, timeout: T)
+    where
+        T: Into<Milliseconds>,
+    {
+        if self.try_start(timeout).is_err() {
+            panic!("timeout is too long for the RTC clock");
+        }
+    }
+
+    fn wait(&mut self) -> nb::Result<(), Void> {
+        if self.is_compare_interrupt() {
+            self.clear_compare_interrupt();
+            Ok(())
+        } else {
+            Err(nb::Error::WouldBlock)
+        }
+    }
+}
+
+impl Rtc<ClockMode> {
+    /// Configure the RTC as a calendar counting in seconds.  See
+    /// `into_clock_mode` for the constraints on `rtc_clock_freq`.
+    pub fn clock_mode(rtc: RTC, rtc_clock_freq: Hertz, mclk: &mut MCLK) -> Self {
+        Rtc::<ClockMode>::attach(rtc, rtc_clock_freq, mclk).reset().into_clock_mode()
+    }
+
+    /// Take over an RTC that already runs as a calendar, such as after a
+    /// reset or a wake up from backup sleep, keeping the time, the
+    /// configuration and the backup registers.  An RTC that is disabled,
+    /// in another mode or counting at another rate is configured as by
+    /// `clock_mode`, which loses the time.
+    pub fn resume_clock_mode(rtc: RTC, rtc_clock_freq: Hertz, mclk: &mut MCLK) -> Self {
+        let rtc = Rtc::<ClockMode>::attach(rtc, rtc_clock_freq, mclk);
+        let ctrl = rtc.mode2().ctrla.read();
+        if ctrl.enable().bit_is_set()
+            && ctrl.mode().is_clock()
+            && Some(ctrl.prescaler().bits()) == clock_prescaler(rtc.rtc_clock_freq)
+        {
+            // Synchronize CLOCK continuously so that it can be read
+            rtc.mode2().ctrla.modify(|_, w| w.clocksync().set_bit());
+            rtc.sync();
+            rtc
+        } else {
+            rtc.reset().into_clock_mode()
+        }
+    }
+
+    /// Returns the current date and time
+    pub fn current_time(&self) -> Datetime {
+        self.sync();
+        let clock = self.mode2().clock.read();
+        Datetime {
+            seconds: clock.second().bits(),
+            minutes: clock.minute().bits(),
+            hours: clock.hour().bits(),
+            day: clock.day().bits(),
+            month: clock.month().bits(),
+            year: clock.year().bits(),
+        }
+    }
+
+    /// Set the current date and time
+    pub fn set_time(&mut self, time: Datetime) {
+        self.mode2().clock.write(|w| unsafe {
+            w.second().bits(time.seconds);
+            w.minute().bits(time.minutes);
+            w.hour().bits(time.hours);
+            w.day().bits(time.day);
+            w.month().bits(time.month);
+            w.year().bits(time.year)
+        });
+        self.sync();
+    }
+
+    /// Set the alarm time and which of its fields must match the current
+    /// time for the alarm to trigger
+    pub fn set_alarm(&mut self, time: Datetime, mask: AlarmMask) {
+        self.mode2().alarm0.write(|w| unsafe {
+            w.second().bits(time.seconds);
+            w.minute().bits(time.minutes);
+            w.hour().bits(time.hours);
+            w.day().bits(time.day);
+            w.month().bits(time.month);
+            w.year().bits(time.year)
+        });
+        self.sync();
+        self.mode2()
+            .mask0
+            .write(|w| unsafe { w.sel().bits(mask as u8) });
+        self.sync();
+    }
+
+    /// Enable the interrupt generation when the alarm triggers.
+    /// This method only configures the RTC to raise the interrupt;
+    /// it does not configure the interrupt controller or define an
+    /// interrupt handler.
+    pub fn enable_alarm_interrupt(&mut self) {
+        self.mode2().intenset.write(|w| w.alarm0().set_bit());
+    }
+
+    /// Disables the alarm interrupt
+    pub fn disable_alarm_interrupt(&mut self) {
+        self.mode2().intenclr.write(|w| w.alarm0().set_bit());
+    }
+
+    /// Returns true if the alarm has triggered since the flag was last
+    /// cleared
+    pub fn is_alarm_interrupt(&self) -> bool {
+        self.mode2().intflag.read().alarm0().bit_is_set()
+    }
+
+    /// Clear the alarm flag
+    pub fn clear_alarm_interrupt(&mut self) {
+        // Writing a 1 clears the flag
+        self.mode2().intflag.write(|w| w.alarm0().set_bit());
+    }
+}
@@ -2,6 +2,8 @@
 
 use crate::clock;
 use crate::time::Hertz;
+use crate::gpio::PeripheralPin;
+use crate::hal::blocking::delay::DelayUs;
 use crate::hal::blocking::i2c::{Read, Write, WriteRead};
 use crate::target_device::sercom0::I2CM;
 use crate::target_device::{MCLK, SERCOM0, SERCOM1, SERCOM2, SERCOM3};
@@ -13,6 +15,137 @@ const BUS_STATE_OWNED: u8 = 2;
 const MASTER_ACT_READ: u8 = 2;
 const MASTER_ACT_STOP: u8 = 3;
 
+/// Release a bus left inactive for 20-21 SCL cycles
+const INACTIVE_TIMEOUT: u8 = 3;
+
+/// The number of times the hardware is polled before a wait gives up
+/// with `I2CError::Timeout`, unless changed with `set_timeout`
+const DEFAULT_TIMEOUT: u32 = 100_000;
+
+/// Half of an SCL period of the bus recovery, in microseconds
+const RECOVERY_HALF_PERIOD_US: u32 = 5;
+
+/// The SCL of the master code that starts a high-speed transfer
+const MASTER_CODE_FREQ: u32 = 400_000;
+
+/// The time SDA is held after the falling edge of SCL
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SdaHold {
+    Disabled,
+    /// 50-100ns
+    Hold75ns,
+    /// 300-600ns
+    Hold450ns,
+    /// 400-800ns
+    Hold600ns,
+}
+
+/// When the master stretches SCL while a received byte is handled
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SclStretch {
+    /// Before the acknowledge bit
+    BeforeAck,
+    /// After the acknowledge bit, which high-speed mode always uses
+    AfterAck,
+}
+
+/// The bus timing of an I2C master:
+///
+/// ```no_run
+/// // A heavily loaded bus with slow edges
+/// let config = I2CConfig::default().rise_time_ns(300);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct I2CConfig {
+    /// The rise time of SCL, which lengthens each SCL period beyond the
+    /// count of the baud generator
+    pub rise_time_ns: u32,
+    pub sda_hold: SdaHold,
+    pub scl_stretch: SclStretch,
+}
+
+impl Default for I2CConfig {
+    /// A rise time of 100ns, no SDA hold time and stretching before the
+    /// acknowledge bit
+    fn default() -> Self {
+        I2CConfig {
+            rise_time_ns: 100,
+            sda_hold: SdaHold::Disabled,
+            scl_stretch: SclStretch::BeforeAck,
+        }
+    }
+}
+
+impl I2CConfig {
+    /// Set the rise time of SCL
+    pub fn rise_time_ns(mut self, rise_time_ns: u32) -> Self {
+        self.rise_time_ns = rise_time_ns;
+        self
+    }
+
+    /// Set the SDA hold time
+    pub fn sda_hold(mut self, sda_hold: SdaHold) -> Self {
+        self.sda_hold = sda_hold;
+        self
+    }
+
+    /// Set when SCL is stretched
+    pub fn scl_stretch(mut self, scl_stretch: SclStretch) -> Self {
+        self.scl_stretch = scl_stretch;
+        self
+    }
+}
+
+/// Returns the high and low counts that divide `fgclk` down to `fscl`,
+/// given the fixed `overhead` of each SCL period in clock cycles.  Above
+/// standard mode the low period is twice the high period, which meets
+/// the minimum low time of fast mode and Fm+.
+fn scl_counts(fgclk: u32, fscl: u32, overhead: u32) -> (u8, u8) {
+    let total = (fgclk / fscl).saturating_sub(overhead);
+    let low = if fscl > 100_000 { total * 2 / 3 } else { total / 2 };
+    let high = total - low;
+    (high.min(255) as u8, low.min(255) as u8)
+}
+
+/// Returns the BAUD and BAUDLOW values producing `fscl` from `fgclk`.
+/// The SCL period lasts 10 + BAUD + BAUDLOW cycles plus the rise time.
+fn scl_baud(fgclk: u32, fscl: u32, rise_time_ns: u32) -> (u8, u8) {
+    let rise = (fgclk as u64 * rise_time_ns as u64 / 1_000_000_000) as u32;
+    scl_counts(fgclk, fscl, 10 + rise)
+}
+
+/// Returns the HSBAUD and HSBAUDLOW values producing `fscl` from
+/// `fgclk`.  The high-speed SCL period lasts 2 + HSBAUD + HSBAUDLOW
+/// cycles.
+fn scl_hs_baud(fgclk: u32, fscl: u32) -> (u8, u8) {
+    scl_counts(fgclk, fscl, 2)
+}
+
+/// The address of an I2C slave
+#[derive(Clone, Copy)]
+enum Address {
+    SevenBit(u8),
+    TenBit(u16),
+}
+
+/// Returns the SMBus packet error code of `bytes`, a CRC-8 with the
+/// polynomial x^8 + x^2 + x + 1, continuing from the code `crc` of the
+/// preceding bytes.  The code of a transaction covers every byte on the
+/// bus, including the address bytes with their R/W bit.
+pub fn smbus_pec(crc: u8, bytes: &[u8]) -> u8 {
+    bytes.iter().fold(crc, |mut crc, byte| {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            };
+        }
+        crc
+    })
+}
+
 /// Define an I2C master type for the given SERCOM and pad pair.
 macro_rules! i2c {
     ([
@@ -25,18 +158,24 @@ pub struct $Type<$pad0, $pad1> {
     sda: $pad0,
     scl: $pad1,
     sercom: $SERCOM,
+    timeout: u32,
+    high_speed: bool,
+    auto_length: bool,
 }
 
 impl<$pad0, $pad1> $Type<$pad0, $pad1> {
     /// Configures the sercom instance to work as an I2C Master.
     /// The clock is obtained via the `GenericClockGenerator` type.
-    /// `freq` specifies the bus frequency to use for I2C communication.
-    /// There are typically a handful of values that tend to be supported;
-    /// standard mode is 100.khz(), full speed mode is 400.khz().
-    /// The hardware in the atsamd device supports fast mode at 1.mhz()
-    /// and fast mode, but there may be additional hardware configuration
-    /// missing from the current software implementation that prevents that
-    /// from working as-written today.
+    /// `freq` specifies the bus frequency to use for I2C communication;
+    /// standard mode is 100.khz() and fast mode is 400.khz().  Up to
+    /// 1.mhz() selects Fast-mode Plus, and above that high-speed mode,
+    /// up to 3.4.mhz().  High-speed mode is only available on the pins
+    /// listed in the datasheet, and each transfer starts with a master
+    /// code sent at 400kHz.
+    ///
+    /// The bus uses the timing of `I2CConfig::default()`; see
+    /// `with_config` for slower edges, an SDA hold time or another
+    /// SCL stretch mode.
     ///
     /// ```no_run
     /// let mut i2c = I2CMaster3::new(
@@ -57,6 +196,23 @@ impl<$pad0, $pad1> $Type<$pad0, $pad1> {
         sda: $pad0,
         scl: $pad1,
     ) -> Self {
+        Self::with_config(clock, freq, sercom, mclk, sda, scl, I2CConfig::default())
+    }
+
+    /// Configures the sercom instance like `new`, with the bus timing
+    /// given by `config`
+    pub fn with_config<F: Into<Hertz>>(
+        clock: &clock::$clock,
+        freq: F,
+        sercom: $SERCOM,
+        mclk: &mut MCLK,
+        sda: $pad0,
+        scl: $pad1,
+        config: I2CConfig,
+    ) -> Self {
+        let freq = freq.into().0;
+        let high_speed = freq > 1_000_000;
+
         // Power up the peripheral bus clock.
         // safe because we're exclusively owning SERCOM
         mclk.$apmask.modify(|_, w| w.$powermask().set_bit());
@@ -74,10 +230,46 @@ impl<$pad0, $pad1> $Type<$pad0, $pad1> {
             // wait for configuration to take effect
             while sercom.i2cm().syncbusy.read().enable().bit_is_set() {}
 
-            // set the baud rate
-            let gclk = clock.freq();
-            let baud = (gclk.0 / (2 * freq.into().0) - 1) as u8;
-            sercom.i2cm().baud.modify(|_, w| w.baud().bits(baud));
+            // set the baud rate; high-speed mode sends the master code
+            // with BAUD and BAUDLOW
+            let gclk = clock.freq().0;
+            let scl = if high_speed { MASTER_CODE_FREQ } else { freq };
+            let (baud, baudlow) = scl_baud(gclk, scl, config.rise_time_ns);
+            let (hsbaud, hsbaudlow) = if high_speed {
+                scl_hs_baud(gclk, freq)
+            } else {
+                (0, 0)
+            };
+            sercom.i2cm().baud.write(|w| {
+                w.baud().bits(baud);
+                w.baudlow().bits(baudlow);
+                w.hsbaud().bits(hsbaud);
+                w.hsbaudlow().bits(hsbaudlow)
+            });
+
+            sercom.i2cm().ctrla.modify(|_, w| {
+                w.speed().bits(match freq {
+                    0..=400_000 => 0,
+                    400_001..=1_000_000 => 1,
+                    _ => 2,
+                });
+                w.sdahold().bits(match config.sda_hold {
+                    SdaHold::Disabled => 0,
+                    SdaHold::Hold75ns => 1,
+                    SdaHold::Hold450ns => 2,
+                    SdaHold::Hold600ns => 3,
+                });
+                w.sclsm().bit(high_speed || config.scl_stretch == SclStretch::AfterAck)
+            });
+
+            // Time out a bus held low, slaves stretching the clock for too
+            // long, and a bus left busy by another master
+            sercom.i2cm().ctrla.modify(|_, w| {
+                w.inactout().bits(INACTIVE_TIMEOUT);
+                w.lowtouten().set_bit();
+                w.mexttoen().set_bit();
+                w.sexttoen().set_bit()
+            });
 
             sercom.i2cm().ctrla.modify(|_, w| w.enable().set_bit());
             // wait for configuration to take effect
@@ -92,7 +284,95 @@ impl<$pad0, $pad1> $Type<$pad0, $pad1> {
             while sercom.i2cm().syncbusy.read().sysop().bit_is_set() {}
         }
 
-        Self { sda, scl, sercom }
+        Self {
+            sda,
+            scl,
+            sercom,
+            timeout: DEFAULT_TIMEOUT,
+            high_speed,
+            auto_length: false,
+        }
+    }
+
+    /// Set the number of times the hardware is polled before a transfer
+    /// gives up with `I2CError::Timeout`.  The time this takes depends on
+    /// the CPU clock; the hardware timeouts usually end a transfer first.
+    pub fn set_timeout(&mut self, polls: u32) {
+        self.timeout = polls;
+    }
+
+    /// Let the hardware count the bytes of reads and writes of up to 255
+    /// bytes with ADDR.LEN.  It then ends reads with a NACK on its own,
+    /// as DMA transfers require, and flags a write cut short by the slave
+    /// as `I2CError::LengthError`.  The write of a `write_read` is not
+    /// counted, so that it can be followed by a repeated start.
+    pub fn set_auto_length(&mut self, enable: bool) {
+        self.auto_length = enable;
+    }
+
+    /// Sends bytes to the slave with the 10-bit address `addr`
+    pub fn write_10bit(&mut self, addr: u16, bytes: &[u8]) -> Result<(), I2CError> {
+        let res = self.do_write(Address::TenBit(addr), bytes);
+        self.cmd_stop();
+        res
+    }
+
+    /// Reads bytes from the slave with the 10-bit address `addr`
+    pub fn read_10bit(&mut self, addr: u16, buffer: &mut [u8]) -> Result<(), I2CError> {
+        let res = self.do_read(Address::TenBit(addr), buffer);
+        self.cmd_stop();
+        res
+    }
+
+    /// Sends bytes to the slave with the 10-bit address `addr`, then
+    /// reads bytes from it after a repeated start
+    pub fn write_read_10bit(&mut self, addr: u16, bytes: &[u8], buffer: &mut [u8]) -> Result<(), I2CError> {
+        let res = self.do_write_read(Address::TenBit(addr), bytes, buffer);
+        self.cmd_stop();
+        res
+    }
+
+    /// Sends an SMBus quick command, which consists of the address and
+    /// the R/W bit only; `read` is the bit of data it carries.
+    ///
+    /// After a read address is acknowledged, the SERCOM always clocks in
+    /// one byte from the slave before it can issue the STOP.  With `read`
+    /// set, that byte is refused with a NACK and discarded, so the slave
+    /// sees a one byte read rather than a strict quick command.  Most
+    /// devices accept this, but ones that implement the quick command
+    /// literally may not.
+    pub fn smbus_quick_command(&mut self, addr: u8, read: bool) -> Result<(), I2CError> {
+        let res = if read {
+            self.start_tx_read(Address::SevenBit(addr), 0)
+        } else {
+            self.start_tx_write(Address::SevenBit(addr), 0)
+        };
+        // NACK the byte the hardware received after a read address
+        self.i2cm().ctrlb.modify(|_, w| w.ackact().set_bit());
+        self.cmd_stop();
+        res
+    }
+
+    /// Sends an SMBus block write: `command`, the byte count and `data`,
+    /// followed by the packet error code if `pec` is set.  Returns
+    /// `I2CError::LengthError` without a transfer if `data` holds more
+    /// than the 255 bytes of an SMBus block.
+    pub fn smbus_block_write(&mut self, addr: u8, command: u8, data: &[u8], pec: bool) -> Result<(), I2CError> {
+        if data.len() > 255 {
+            return Err(I2CError::LengthError);
+        }
+        let res = self.do_smbus_block_write(addr, command, data, pec);
+        self.cmd_stop();
+        res
+    }
+
+    /// Performs an SMBus block read: sends `command`, then reads the byte
+    /// count and as many bytes into `buffer`, followed by the packet error
+    /// code if `pec` is set.  Returns the number of bytes read.
+    pub fn smbus_block_read(&mut self, addr: u8, command: u8, buffer: &mut [u8], pec: bool) -> Result<usize, I2CError> {
+        let res = self.do_smbus_block_read(addr, command, buffer, pec);
+        self.cmd_stop();
+        res
     }
 
     /// Breaks the sercom device up into its constituent pins and the SERCOM
@@ -101,23 +381,51 @@ impl<$pad0, $pad1> $Type<$pad0, $pad1> {
         (self.sda, self.scl, self.sercom)
     }
 
-    fn start_tx_write(&mut self, addr: u8) -> Result<(), I2CError> {
-        loop {
-            match self.i2cm().status.read().busstate().bits() {
-                BUS_STATE_IDLE | BUS_STATE_OWNED => break,
-                _ => continue,
+    /// Poll `done` until it returns true or the timeout expires
+    fn wait<F: Fn(&I2CM) -> bool>(&mut self, done: F) -> Result<(), I2CError> {
+        for _ in 0..self.timeout {
+            if done(self.i2cm()) {
+                return Ok(());
             }
         }
+        Err(I2CError::Timeout)
+    }
+
+    fn wait_bus_ready(&mut self) -> Result<(), I2CError> {
+        self.wait(|i2cm| match i2cm.status.read().busstate().bits() {
+            BUS_STATE_IDLE | BUS_STATE_OWNED => true,
+            _ => false,
+        })
+    }
 
-        // Signal start and transmit encoded address.
+    /// Signal start (or repeated start) and transmit the encoded address.
+    /// The hardware sends a 10-bit address for writing before it turns
+    /// around for a read.  Auto length counts `len` bytes when enabled.
+    fn send_address(&mut self, addr: Address, read: bool, len: usize) {
+        let (addr, ten_bit) = match addr {
+            Address::SevenBit(addr) => (addr as u16, false),
+            Address::TenBit(addr) => (addr, true),
+        };
+        let high_speed = self.high_speed;
+        let auto_length = self.auto_length && len > 0 && len <= 255;
         unsafe {
-            self.i2cm()
-                .addr
-                .write(|w| w.addr().bits((addr as u16) << 1));
+            self.i2cm().addr.write(|w| {
+                w.addr().bits((addr << 1) | read as u16);
+                w.tenbiten().bit(ten_bit);
+                w.hs().bit(high_speed);
+                w.lenen().bit(auto_length);
+                w.len().bits(len as u8)
+            });
         }
+    }
+
+    fn start_tx_write(&mut self, addr: Address, len: usize) -> Result<(), I2CError> {
+        self.wait_bus_ready()?;
+
+        self.send_address(addr, false, len);
 
         // wait for transmission to complete
-        while !self.i2cm().intflag.read().mb().bit_is_set() {}
+        self.wait(|i2cm| i2cm.intflag.read().mb().bit_is_set())?;
 
         self.status_to_err()
     }
@@ -138,38 +446,28 @@ impl<$pad0, $pad1> $Type<$pad0, $pad1> {
         {
             return Err(I2CError::Timeout);
         }
+        if status.lenerr().bit_is_set() {
+            return Err(I2CError::LengthError);
+        }
 
         Ok(())
     }
 
-    fn start_tx_read(&mut self, addr: u8) -> Result<(), I2CError> {
-        loop {
-            match self.i2cm().status.read().busstate().bits() {
-                BUS_STATE_IDLE | BUS_STATE_OWNED => break,
-                _ => continue,
-            }
-        }
+    fn start_tx_read(&mut self, addr: Address, len: usize) -> Result<(), I2CError> {
+        self.wait_bus_ready()?;
 
         self.i2cm().intflag.modify(|_, w| w.error().clear_bit());
 
-        // Signal start (or rep start if appropriate)
-        // and transmit encoded address.
-        unsafe {
-            self.i2cm()
-                .addr
-                .write(|w| w.addr().bits(((addr as u16) << 1) | 1));
-        }
+        self.send_address(addr, true, len);
 
         // wait for transmission to complete
-        loop {
-            let intflag = self.i2cm().intflag.read();
-            // If arbitration was lost, it will be signalled via the mb bit
-            if intflag.mb().bit_is_set() {
-                return Err(I2CError::ArbitrationLost);
-            }
-            if intflag.sb().bit_is_set() || intflag.error().bit_is_set() {
-                break;
-            }
+        self.wait(|i2cm| {
+            let intflag = i2cm.intflag.read();
+            intflag.mb().bit_is_set() || intflag.sb().bit_is_set() || intflag.error().bit_is_set()
+        })?;
+        // If arbitration was lost, it will be signalled via the mb bit
+        if self.i2cm().intflag.read().mb().bit_is_set() {
+            return Err(I2CError::ArbitrationLost);
         }
 
         self.status_to_err()
@@ -211,26 +509,24 @@ impl<$pad0, $pad1> $Type<$pad0, $pad1> {
                 self.i2cm().data.write(|w| w.bits(*b as u32));
             }
 
-            loop {
-                let intflag = self.i2cm().intflag.read();
-                if intflag.mb().bit_is_set() || intflag.error().bit_is_set() {
-                    break;
-                }
-            }
+            self.wait(|i2cm| {
+                let intflag = i2cm.intflag.read();
+                intflag.mb().bit_is_set() || intflag.error().bit_is_set()
+            })?;
             self.status_to_err()?;
         }
         Ok(())
     }
 
-    fn read_one(&mut self) -> u8 {
-        while !self.i2cm().intflag.read().sb().bit_is_set() {}
-        self.i2cm().data.read().bits() as u8
+    fn read_one(&mut self) -> Result<u8, I2CError> {
+        self.wait(|i2cm| i2cm.intflag.read().sb().bit_is_set())?;
+        Ok(self.i2cm().data.read().bits() as u8)
     }
 
     fn fill_buffer(&mut self, buffer: &mut [u8]) -> Result<(), I2CError> {
         // Some manual iterator gumph because we need to ack bytes after the first.
         let mut iter = buffer.iter_mut();
-        *iter.next().expect("buffer len is at least 1") = self.read_one();
+        *iter.next().expect("buffer len is at least 1") = self.read_one()?;
 
         loop {
             match iter.next() {
@@ -238,7 +534,7 @@ impl<$pad0, $pad1> $Type<$pad0, $pad1> {
                 Some(dest) => {
                     // Ack the last byte so that we can receive another one
                     self.cmd_read();
-                    *dest = self.read_one();
+                    *dest = self.read_one()?;
                 }
             }
         }
@@ -250,29 +546,127 @@ impl<$pad0, $pad1> $Type<$pad0, $pad1> {
         Ok(())
     }
 
-    fn do_write(&mut self, addr: u8, bytes: &[u8]) -> Result<(), I2CError> {
-        self.start_tx_write(addr)?;
+    fn do_write(&mut self, addr: Address, bytes: &[u8]) -> Result<(), I2CError> {
+        self.start_tx_write(addr, bytes.len())?;
         self.send_bytes(bytes)
     }
 
-    fn do_read(&mut self, addr: u8, buffer: &mut [u8]) -> Result<(), I2CError> {
-        self.start_tx_read(addr)?;
+    fn do_read(&mut self, addr: Address, buffer: &mut [u8]) -> Result<(), I2CError> {
+        self.start_tx_read(addr, buffer.len())?;
         self.fill_buffer(buffer)
     }
 
-    fn do_write_read(&mut self, addr: u8, bytes: &[u8], buffer: &mut [u8]) -> Result<(), I2CError> {
-        self.start_tx_write(addr)?;
+    fn do_write_read(&mut self, addr: Address, bytes: &[u8], buffer: &mut [u8]) -> Result<(), I2CError> {
+        self.start_tx_write(addr, 0)?;
         self.send_bytes(bytes)?;
-        self.start_tx_read(addr)?;
+        self.start_tx_read(addr, buffer.len())?;
         self.fill_buffer(buffer)
     }
+
+    fn do_smbus_block_write(&mut self, addr: u8, command: u8, data: &[u8], pec: bool) -> Result<(), I2CError> {
+        let header = [command, data.len() as u8];
+        self.start_tx_write(Address::SevenBit(addr), 0)?;
+        self.send_bytes(&header)?;
+        self.send_bytes(data)?;
+        if pec {
+            let crc = smbus_pec(smbus_pec(0, &[addr << 1]), &header);
+            self.send_bytes(&[smbus_pec(crc, data)])?;
+        }
+        Ok(())
+    }
+
+    fn do_smbus_block_read(&mut self, addr: u8, command: u8, buffer: &mut [u8], pec: bool) -> Result<usize, I2CError> {
+        self.start_tx_write(Address::SevenBit(addr), 0)?;
+        self.send_bytes(&[command])?;
+        self.start_tx_read(Address::SevenBit(addr), 0)?;
+
+        let count = self.read_one()?;
+        let len = count as usize;
+        if len > buffer.len() {
+            self.i2cm().ctrlb.modify(|_, w| w.ackact().set_bit());
+            return Err(I2CError::LengthError);
+        }
+        for dest in buffer[..len].iter_mut() {
+            // Ack the last byte so that we can receive another one
+            self.cmd_read();
+            *dest = self.read_one()?;
+        }
+        let received = if pec {
+            self.cmd_read();
+            Some(self.read_one()?)
+        } else {
+            None
+        };
+
+        // arrange to send nack on the stop
+        self.i2cm().ctrlb.modify(|_, w| w.ackact().set_bit());
+
+        if let Some(received) = received {
+            let crc = smbus_pec(0, &[addr << 1, command, (addr << 1) | 1, count]);
+            if smbus_pec(crc, &buffer[..len]) != received {
+                return Err(I2CError::PecMismatch);
+            }
+        }
+        Ok(len)
+    }
 }
+
+impl<$pad0: PeripheralPin, $pad1: PeripheralPin> $Type<$pad0, $pad1> {
+    /// Free a bus held low by a slave that was interrupted in the middle
+    /// of a byte.  The pads are taken from the SERCOM while up to nine
+    /// clock pulses are sent, until the slave releases SDA, followed by a
+    /// stop condition.  `delay` paces the pulses at about 100kHz.
+    /// Returns `I2CError::BusError` if SDA is still held low afterwards.
+    pub fn recover_bus<D: DelayUs<u32>>(&mut self, delay: &mut D) -> Result<(), I2CError> {
+        let sda = self.sda.detach();
+        let scl = self.scl.detach();
+        delay.delay_us(RECOVERY_HALF_PERIOD_US);
+
+        for _ in 0..9 {
+            if self.sda.level() {
+                break;
+            }
+            self.scl.drive_low();
+            delay.delay_us(RECOVERY_HALF_PERIOD_US);
+            self.scl.release();
+            delay.delay_us(RECOVERY_HALF_PERIOD_US);
+        }
+
+        // A stop condition is SDA rising while SCL is high
+        self.scl.drive_low();
+        self.sda.drive_low();
+        delay.delay_us(RECOVERY_HALF_PERIOD_US);
+        self.scl.release();
+        delay.delay_us(RECOVERY_HALF_PERIOD_US);
+        self.sda.release();
+        delay.delay_us(RECOVERY_HALF_PERIOD_US);
+        let released = self.sda.level();
+
+        self.sda.attach(sda);
+        self.scl.attach(scl);
+
+        // The SERCOM lost track of the bus meanwhile
+        unsafe {
+            self.i2cm()
+                .status
+                .modify(|_, w| w.busstate().bits(BUS_STATE_IDLE));
+        }
+        self.wait_sync();
+
+        if released {
+            Ok(())
+        } else {
+            Err(I2CError::BusError)
+        }
+    }
+}
+
 impl<$pad0, $pad1> Write for $Type<$pad0, $pad1> {
     type Error = I2CError;
 
     /// Sends bytes to slave with address `addr`
     fn write(&mut self, addr: u8, bytes: &[u8]) -> Result<(), Self::Error> {
-        let res = self.do_write(addr, bytes);
+        let res = self.do_write(Address::SevenBit(addr), bytes);
         self.cmd_stop();
         res
     }
@@ -282,7 +676,7 @@ impl<$pad0, $pad1> Read for $Type<$pad0, $pad1> {
     type Error = I2CError;
 
     fn read(&mut self, addr: u8, buffer: &mut [u8]) -> Result<(), Self::Error> {
-        let res = self.do_read(addr, buffer);
+        let res = self.do_read(Address::SevenBit(addr), buffer);
         self.cmd_stop();
         res
     }
@@ -292,7 +686,7 @@ impl<$pad0, $pad1> WriteRead for $Type<$pad0, $pad1> {
     type Error = I2CError;
 
     fn write_read(&mut self, addr: u8, bytes: &[u8], buffer: &mut [u8]) -> Result<(), Self::Error> {
-        let res = self.do_write_read(addr, bytes, buffer);
+        let res = self.do_write_read(Address::SevenBit(addr), bytes, buffer);
         self.cmd_stop();
         res
     }
@@ -368,4 +762,36 @@ pub enum I2CError {
     BusError,
     Timeout,
     Nack,
+    /// The slave ended a transfer before the expected number of bytes,
+    /// or a transfer is too long for the hardware or the protocol
+    LengthError,
+    /// The SMBus packet error code did not match the data received
+    PecMismatch,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pec_is_crc8() {
+        // The check value of CRC-8 with the SMBus polynomial
+        assert_eq!(smbus_pec(0, b"123456789"), 0xf4);
+        assert_eq!(smbus_pec(0, &[]), 0);
+        // A code can be continued over the following bytes
+        assert_eq!(smbus_pec(smbus_pec(0, b"1234"), b"56789"), 0xf4);
+    }
+
+    #[test]
+    fn scl_periods() {
+        // Standard mode splits the period evenly
+        assert_eq!(scl_baud(48_000_000, 100_000, 0), (235, 235));
+        // Faster modes keep SCL low for two thirds of the period, less
+        // the rise time of 4 cycles
+        assert_eq!(scl_baud(48_000_000, 400_000, 100), (36, 70));
+        assert_eq!(scl_baud(48_000_000, 1_000_000, 0), (13, 25));
+        assert_eq!(scl_hs_baud(48_000_000, 3_400_000), (4, 8));
+        // Slow clocks saturate the 8-bit registers
+        assert_eq!(scl_baud(48_000_000, 10_000, 0), (255, 255));
+    }
 }
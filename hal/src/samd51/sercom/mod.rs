@@ -9,11 +9,23 @@
 //! peripheral function mode they are routed to the sercom pad.
 
 mod i2c;
+mod i2c_slave;
 mod pads;
 mod spi;
-mod uart;
+mod spi_slave;
+pub mod uart;
 
 pub use self::i2c::*;
+pub use self::i2c_slave::*;
 pub use self::pads::*;
 pub use self::spi::*;
-pub use self::uart::*;
+pub use self::spi_slave::*;
+// The UART errors are reached as `uart::Error` and `uart::ConfigError`,
+// leaving the unqualified `Error` to the SPI
+pub use self::uart::{
+    BitOrder, BufferedRx, BufferedTx, CharSize, Parity, RingBuffer, RxpoTxpo, SampleRate,
+    StopBits, UartConfig, UartHalf, UartInterrupt,
+};
+pub use self::uart::{UART0, UART0Padout, UART0Rx, UART0Tx, UART1, UART1Padout, UART1Rx, UART1Tx};
+pub use self::uart::{UART2, UART2Padout, UART2Rx, UART2Tx, UART3, UART3Padout, UART3Rx, UART3Tx};
+pub use self::uart::{UART4, UART4Padout, UART4Rx, UART4Tx, UART5, UART5Padout, UART5Rx, UART5Tx};
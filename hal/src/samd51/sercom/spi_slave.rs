@@ -0,0 +1,316 @@
+use crate::clock;
+use crate::hal::spi::{FullDuplex, Mode, Phase, Polarity};
+use nb;
+use crate::sercom::pads::*;
+use crate::sercom::DipoDopo;
+use super::spi::Error;
+use crate::target_device::sercom0::SPI;
+use crate::target_device::{MCLK, SERCOM0, SERCOM1, SERCOM2, SERCOM3};
+use crate::target_device::{SERCOM4, SERCOM5};
+
+/// The way an SPI slave matches the address sent by the master as the
+/// first character of each transaction.  Transactions with another
+/// address are ignored.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AddressMode {
+    /// Match `address`, ignoring the bits that are set in `mask`
+    Mask { address: u8, mask: u8 },
+    /// Match either of the two addresses
+    TwoAddresses(u8, u8),
+    /// Match any address from `low` up to and including `high`
+    Range { low: u8, high: u8 },
+}
+
+/// The SPI slave interrupts that can be enabled
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpiSlaveInterrupt {
+    /// The data register can take the next character to send
+    DataRegisterEmpty,
+    /// The master deasserted SS, ending the transaction
+    TransmitComplete,
+    /// A received character can be read
+    ReceiveComplete,
+    /// The master asserted SS, starting a transaction
+    SlaveSelectLow,
+    /// The receive buffer overflowed
+    Error,
+}
+
+/// Define an SPISlaveX type for the given Sercom number.
+///
+/// Also defines the valid "pad to spi function" mappings for this instance so
+/// that construction is restricted to correct configurations.
+macro_rules! spi_slave {
+    ($Type:ident: ($Sercom:ident, $SERCOM:ident, $powermask:ident, $clock:ident, $apmask:ident)) => {
+        $crate::paste::item! {
+            /// A pad mapping configuration for the SERCOM in SPI slave mode.
+            ///
+            /// This type can only be constructed using the From implementations
+            /// in this module, which are restricted to valid configurations.
+            ///
+            /// Defines which sercom pad is mapped to which SPI function.
+            pub struct [<$Type Padout>]<MISO, MOSI, SCK, SS> {
+                _miso: MISO,
+                _mosi: MOSI,
+                _sck: SCK,
+                _ss: SS,
+            }
+        }
+
+        /// Define a From instance for a tuple of SercomXPadX instances that
+        /// converts them into an SPISlaveXPadout instance.
+        ///
+        /// Also defines a DipoDopo instance for the constructed padout instance
+        /// that returns the values used to configure the sercom pads for the
+        /// appropriate function in the sercom register file.
+        macro_rules! padout {
+            ($dipo_dopo:expr => $pad0:ident, $pad1:ident, $pad2:ident, $pad3:ident) => {
+                $crate::paste::item! {
+                    /// Convert from a tuple of (MISO, MOSI, SCK, SS) to SPISlaveXPadout
+                    impl<PIN0, PIN1, PIN2, PIN3> From<([<$Sercom $pad0>]<PIN0>, [<$Sercom $pad1>]<PIN1>, [<$Sercom $pad2>]<PIN2>, [<$Sercom $pad3>]<PIN3>)> for [<$Type Padout>]<[<$Sercom $pad0>]<PIN0>, [<$Sercom $pad1>]<PIN1>, [<$Sercom $pad2>]<PIN2>, [<$Sercom $pad3>]<PIN3>> {
+                        fn from(pads: ([<$Sercom $pad0>]<PIN0>, [<$Sercom $pad1>]<PIN1>, [<$Sercom $pad2>]<PIN2>, [<$Sercom $pad3>]<PIN3>)) -> [<$Type Padout>]<[<$Sercom $pad0>]<PIN0>, [<$Sercom $pad1>]<PIN1>, [<$Sercom $pad2>]<PIN2>, [<$Sercom $pad3>]<PIN3>> {
+                            [<$Type Padout>] { _miso: pads.0, _mosi: pads.1, _sck: pads.2, _ss: pads.3 }
+                        }
+                    }
+
+                    impl<PIN0, PIN1, PIN2, PIN3> DipoDopo for [<$Type Padout>]<[<$Sercom $pad0>]<PIN0>, [<$Sercom $pad1>]<PIN1>, [<$Sercom $pad2>]<PIN2>, [<$Sercom $pad3>]<PIN3>> {
+                        fn dipo_dopo(&self) -> (u8, u8) {
+                            $dipo_dopo
+                        }
+                    }
+                }
+            };
+        }
+
+        // In slave mode DOPO places MISO, SCK and SS, and DIPO takes
+        // MOSI from the remaining pad.  DOPO 1 and 3 are reserved.
+        padout!((3, 0) => Pad0, Pad3, Pad1, Pad2);
+        padout!((0, 2) => Pad3, Pad0, Pad1, Pad2);
+
+        $crate::paste::item! {
+            /// SPISlaveX represents the corresponding SERCOMX instance
+            /// configured to act in the role of an SPI Slave.
+            /// Objects of this type implement the HAL `FullDuplex` trait;
+            /// the clock is driven by the master, so `send` only preloads
+            /// the character shifted out by the next transfer.
+            ///
+            /// This type is generic over any valid pad mapping where there is
+            /// a defined "data in pin out data out pin out" implementation.
+            pub struct $Type<MISO, MOSI, SCK, SS> {
+                padout: [<$Type Padout>]<MISO, MOSI, SCK, SS>,
+                sercom: $SERCOM,
+            }
+
+            impl<MISO, MOSI, SCK, SS> $Type<MISO, MOSI, SCK, SS> {
+                /// Power on and configure SERCOMX to work as an SPI Slave
+                /// using the specified SPI Mode. The padout specifies which
+                /// pins are bound to the MISO, MOSI, SCK and SS functions.
+                ///
+                /// You can use a tuple of four SercomXPadY instances for which
+                /// there exists a From implementation for SPISlaveXPadout.
+                pub fn new<T: Into<[<$Type Padout>]<MISO, MOSI, SCK, SS>>>(
+                    clock: &clock::$clock,
+                    mode: Mode,
+                    sercom: $SERCOM,
+                    mclk: &mut MCLK,
+                    padout: T,
+                ) -> Self where
+                    [<$Type Padout>]<MISO, MOSI, SCK, SS>: DipoDopo {
+                    Self::configure(clock, mode, sercom, mclk, padout.into(), None)
+                }
+
+                /// Power on and configure SERCOMX like `new`, taking part
+                /// only in the transactions whose first character matches
+                /// `address`.  The matching address is received as the
+                /// first character.
+                pub fn with_address<T: Into<[<$Type Padout>]<MISO, MOSI, SCK, SS>>>(
+                    clock: &clock::$clock,
+                    mode: Mode,
+                    sercom: $SERCOM,
+                    mclk: &mut MCLK,
+                    padout: T,
+                    address: AddressMode,
+                ) -> Self where
+                    [<$Type Padout>]<MISO, MOSI, SCK, SS>: DipoDopo {
+                    Self::configure(clock, mode, sercom, mclk, padout.into(), Some(address))
+                }
+
+                fn configure(
+                    _clock: &clock::$clock,
+                    mode: Mode,
+                    sercom: $SERCOM,
+                    mclk: &mut MCLK,
+                    padout: [<$Type Padout>]<MISO, MOSI, SCK, SS>,
+                    address: Option<AddressMode>,
+                ) -> Self where
+                    [<$Type Padout>]<MISO, MOSI, SCK, SS>: DipoDopo {
+                    // Power up the peripheral bus clock.
+                    // safe because we're exclusively owning SERCOM
+                    mclk.$apmask.modify(|_, w| w.$powermask().set_bit());
+
+                    unsafe {
+                        // reset the sercom instance
+                        sercom.spi().ctrla.modify(|_, w| w.swrst().set_bit());
+                        // wait for reset to complete
+                        while sercom.spi().syncbusy.read().swrst().bit_is_set()
+                            || sercom.spi().ctrla.read().swrst().bit_is_set()
+                        {}
+
+                        // Put the hardware into spi slave mode
+                        sercom.spi().ctrla.modify(|_, w| w.mode().spi_slave());
+                        // wait for configuration to take effect
+                        while sercom.spi().syncbusy.read().enable().bit_is_set() {}
+
+                        if let Some(address) = address {
+                            let (amode, addr, addrmask) = match address {
+                                AddressMode::Mask { address, mask } => (0, address, mask),
+                                AddressMode::TwoAddresses(first, second) => (1, first, second),
+                                AddressMode::Range { low, high } => (2, low, high),
+                            };
+                            sercom.spi().addr.write(|w| {
+                                w.addr().bits(addr);
+                                w.addrmask().bits(addrmask)
+                            });
+                            sercom.spi().ctrlb.modify(|_, w| w.amode().bits(amode));
+                        }
+
+                        // 8 bit data size, preload the first character to
+                        // send, detect SS going low and enable the receiver
+                        sercom.spi().ctrlb.modify(|_, w|{
+                            w.chsize().bits(0);
+                            w.ploaden().set_bit();
+                            w.ssde().set_bit();
+                            w.rxen().set_bit()
+                        });
+
+                        sercom.spi().ctrla.modify(|_, w| {
+                            match mode.polarity {
+                                Polarity::IdleLow => w.cpol().clear_bit(),
+                                Polarity::IdleHigh => w.cpol().set_bit(),
+                            };
+
+                            match mode.phase {
+                                Phase::CaptureOnFirstTransition => w.cpha().clear_bit(),
+                                Phase::CaptureOnSecondTransition => w.cpha().set_bit(),
+                            };
+
+                            let (dipo, dopo) = padout.dipo_dopo();
+                            w.dipo().bits(dipo);
+                            w.dopo().bits(dopo);
+
+                            // SPI frame, with an address when matching one
+                            w.form().bits(if address.is_some() { 2 } else { 0 });
+
+                            // MSB first
+                            w.dord().clear_bit()
+                        });
+
+                        sercom.spi().ctrla.modify(|_, w| w.enable().set_bit());
+                        // wait for configuration to take effect
+                        while sercom.spi().syncbusy.read().enable().bit_is_set() {}
+                    }
+
+                    Self {
+                        padout,
+                        sercom,
+                    }
+                }
+
+                /// Returns true once the master has selected this slave
+                /// since the last call
+                pub fn selected(&mut self) -> bool {
+                    let selected = self.spi().intflag.read().ssl().bit_is_set();
+                    if selected {
+                        self.spi().intflag.write(|w| w.ssl().set_bit());
+                    }
+                    selected
+                }
+
+                /// Returns true once the master has ended a transaction by
+                /// deselecting this slave since the last call
+                pub fn transaction_complete(&mut self) -> bool {
+                    let complete = self.spi().intflag.read().txc().bit_is_set();
+                    if complete {
+                        self.spi().intflag.write(|w| w.txc().set_bit());
+                    }
+                    complete
+                }
+
+                /// Enable the interrupt generation for `interrupt`.
+                /// This method only configures the SERCOM to raise the
+                /// interrupt; it does not configure the interrupt controller
+                /// or define an interrupt handler.
+                pub fn enable_interrupt(&mut self, interrupt: SpiSlaveInterrupt) {
+                    self.spi().intenset.write(|w| match interrupt {
+                        SpiSlaveInterrupt::DataRegisterEmpty => w.dre().set_bit(),
+                        SpiSlaveInterrupt::TransmitComplete => w.txc().set_bit(),
+                        SpiSlaveInterrupt::ReceiveComplete => w.rxc().set_bit(),
+                        SpiSlaveInterrupt::SlaveSelectLow => w.ssl().set_bit(),
+                        SpiSlaveInterrupt::Error => w.error().set_bit(),
+                    });
+                }
+
+                /// Disable the interrupt generation for `interrupt`
+                pub fn disable_interrupt(&mut self, interrupt: SpiSlaveInterrupt) {
+                    self.spi().intenclr.write(|w| match interrupt {
+                        SpiSlaveInterrupt::DataRegisterEmpty => w.dre().set_bit(),
+                        SpiSlaveInterrupt::TransmitComplete => w.txc().set_bit(),
+                        SpiSlaveInterrupt::ReceiveComplete => w.rxc().set_bit(),
+                        SpiSlaveInterrupt::SlaveSelectLow => w.ssl().set_bit(),
+                        SpiSlaveInterrupt::Error => w.error().set_bit(),
+                    });
+                }
+
+                /// Tear down the SPI instance and yield the constituent pins and
+                /// SERCOM instance.  No explicit de-initialization is performed.
+                pub fn free(self) -> ([<$Type Padout>]<MISO, MOSI, SCK, SS>, $SERCOM) {
+                    (self.padout, self.sercom)
+                }
+
+                /// Helper for accessing the spi member of the sercom instance
+                fn spi(&mut self) -> &SPI {
+                    &self.sercom.spi()
+                }
+            }
+        }
+
+        impl<MISO, MOSI, SCK, SS> FullDuplex<u8> for $Type<MISO, MOSI, SCK, SS> {
+            type Error = Error;
+
+            fn read(&mut self) -> nb::Result<u8, Error> {
+                let status = self.spi().status.read();
+                if status.bufovf().bit_is_set() {
+                    self.spi().status.write(|w| w.bufovf().set_bit());
+                    self.spi().intflag.write(|w| w.error().set_bit());
+                    return Err(nb::Error::Other(Error::Overrun));
+                }
+
+                let intflag = self.spi().intflag.read();
+                // rxc is receive complete
+                if intflag.rxc().bit_is_set() {
+                    Ok(self.spi().data.read().data().bits() as u8)
+                } else {
+                    Err(nb::Error::WouldBlock)
+                }
+            }
+
+            fn send(&mut self, byte: u8) -> nb::Result<(), Error> {
+                let intflag = self.spi().intflag.read();
+                // dre is data register empty
+                if intflag.dre().bit_is_set() {
+                    self.spi().data.write(|w| unsafe{w.data().bits(byte as u32)});
+                    Ok(())
+                } else {
+                    Err(nb::Error::WouldBlock)
+                }
+            }
+        }
+    };
+}
+
+spi_slave!(SPISlave0: (Sercom0, SERCOM0, sercom0_, Sercom0CoreClock, apbamask));
+spi_slave!(SPISlave1: (Sercom1, SERCOM1, sercom1_, Sercom1CoreClock, apbamask));
+spi_slave!(SPISlave2: (Sercom2, SERCOM2, sercom2_, Sercom2CoreClock, apbbmask));
+spi_slave!(SPISlave3: (Sercom3, SERCOM3, sercom3_, Sercom3CoreClock, apbbmask));
+spi_slave!(SPISlave4: (Sercom4, SERCOM4, sercom4_, Sercom4CoreClock, apbdmask));
+spi_slave!(SPISlave5: (Sercom5, SERCOM5, sercom5_, Sercom5CoreClock, apbdmask));
@@ -0,0 +1,210 @@
+//! Working with the SmartEEPROM of the NVM controller.
+//!
+//! The SmartEEPROM emulates a byte-addressable EEPROM in a region at the
+//! end of the flash, handling wear levelling in hardware.  Its contents
+//! are accessed through a virtual address space at `SEEPROM_ADDR`.
+//!
+//! The SmartEEPROM is allocated by the SBLK and PSZ fuses of the user
+//! row, which set the number of flash blocks per sector and the virtual
+//! size.  With the default fuses no SmartEEPROM is allocated and
+//! `SmartEeprom::new` fails with `Error::NotAllocated`.
+//!
+//! ```no_run
+//! let mut nvm = Nvm::new(peripherals.NVMCTRL);
+//! let mut eeprom = SmartEeprom::new(&mut nvm).unwrap();
+//! eeprom.write(0, &calibration).unwrap();
+//! ```
+use crate::nvm::{self, Nvm};
+use core::ptr;
+
+/// The address of the SmartEEPROM virtual address space
+pub const SEEPROM_ADDR: usize = 0x4400_0000;
+
+/// Errors that may occur accessing the SmartEEPROM
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// No SmartEEPROM is allocated in the user row fuses
+    NotAllocated,
+    /// The PSZ fuse selects a virtual size that does not fit in the
+    /// sectors allocated by the SBLK fuse
+    InvalidFuses,
+    /// The access is outside the virtual size
+    OutOfRange,
+    /// Writing is locked
+    Locked,
+    /// A write was discarded because the active sector is full and
+    /// the automatic reallocation is disabled
+    Overflow,
+    /// The NVM controller reported an error
+    Nvm(nvm::Error),
+}
+
+impl From<nvm::Error> for Error {
+    fn from(e: nvm::Error) -> Self {
+        Error::Nvm(e)
+    }
+}
+
+/// How writes to the SmartEEPROM are committed to the flash
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WriteMode {
+    /// Every write is committed to the flash immediately
+    Unbuffered,
+    /// Writes to the same page are gathered in the page buffer and
+    /// committed when a write moves to another page, or on `flush`
+    Buffered,
+}
+
+/// Returns the virtual size selected by the PSZ fuse, checking that the
+/// SBLK fuse allocates enough blocks per sector to hold it
+fn virtual_size(sblk: u8, psz: u8) -> Result<usize, Error> {
+    // The smallest SBLK for each PSZ, from the SmartEEPROM virtual size
+    // table of the datasheet; SBLK values above 10 are not supported
+    let min_sblk = match psz {
+        0..=3 => 1,
+        4 => 2,
+        5 => 3,
+        6 => 5,
+        _ => 9,
+    };
+    if sblk < min_sblk || sblk > 10 {
+        return Err(Error::InvalidFuses);
+    }
+    Ok(512 << psz)
+}
+
+/// `SmartEeprom` provides access to the SmartEEPROM while borrowing the
+/// `Nvm`.
+pub struct SmartEeprom<'a> {
+    nvm: &'a mut Nvm,
+    virtual_size: usize,
+}
+
+impl<'a> SmartEeprom<'a> {
+    /// Access the SmartEEPROM.  Fails if the fuses do not allocate a
+    /// SmartEEPROM, or allocate too few blocks for its virtual size.
+    pub fn new(nvm: &'a mut Nvm) -> Result<Self, Error> {
+        if nvm.nvm.param.read().see().bit_is_clear() {
+            return Err(Error::NotAllocated);
+        }
+        let seestat = nvm.nvm.seestat.read();
+        if seestat.sblk().bits() == 0 {
+            return Err(Error::NotAllocated);
+        }
+        let virtual_size = virtual_size(seestat.sblk().bits(), seestat.psz().bits())?;
+        Ok(Self { nvm, virtual_size })
+    }
+
+    /// Returns the number of flash blocks in each of the two sectors,
+    /// as configured by the SBLK fuse
+    pub fn blocks_per_sector(&self) -> u8 {
+        self.nvm.nvm.seestat.read().sblk().bits()
+    }
+
+    /// Returns the value of the PSZ fuse, which selects the virtual page
+    /// size and with it the virtual size
+    pub fn page_size_fuse(&self) -> u8 {
+        self.nvm.nvm.seestat.read().psz().bits()
+    }
+
+    /// Returns the number of bytes of the SmartEEPROM
+    pub fn virtual_size(&self) -> usize {
+        self.virtual_size
+    }
+
+    /// Returns the current write mode
+    pub fn write_mode(&self) -> WriteMode {
+        if self.nvm.nvm.seecfg.read().wmode().is_buffered() {
+            WriteMode::Buffered
+        } else {
+            WriteMode::Unbuffered
+        }
+    }
+
+    /// Set the write mode
+    pub fn set_write_mode(&mut self, mode: WriteMode) {
+        self.nvm.nvm.seecfg.modify(|_, w| match mode {
+            WriteMode::Unbuffered => w.wmode().unbuffered(),
+            WriteMode::Buffered => w.wmode().buffered(),
+        });
+    }
+
+    /// Returns true if writing to the SmartEEPROM is locked
+    pub fn is_locked(&self) -> bool {
+        self.nvm.nvm.seestat.read().lock().bit_is_set()
+    }
+
+    /// Lock writing to the SmartEEPROM
+    pub fn lock(&mut self) -> Result<(), Error> {
+        Ok(self.nvm.command(0, |w| w.cmd().lsee())?)
+    }
+
+    /// Unlock writing to the SmartEEPROM
+    pub fn unlock(&mut self) -> Result<(), Error> {
+        Ok(self.nvm.command(0, |w| w.cmd().usee())?)
+    }
+
+    /// Lock the SmartEEPROM configuration registers until the next reset
+    pub fn lock_registers(&mut self) -> Result<(), Error> {
+        Ok(self.nvm.command(0, |w| w.cmd().lseer())?)
+    }
+
+    fn wait_busy(&self) {
+        while self.nvm.nvm.seestat.read().busy().bit_is_set() {}
+    }
+
+    fn check_range(&self, offset: usize, len: usize) -> Result<(), Error> {
+        let in_range = match offset.checked_add(len) {
+            Some(end) => end <= self.virtual_size,
+            None => false,
+        };
+        if !in_range {
+            Err(Error::OutOfRange)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Read `data.len()` bytes starting at `offset`
+    pub fn read(&self, offset: usize, data: &mut [u8]) -> Result<(), Error> {
+        self.check_range(offset, data.len())?;
+        let base = (SEEPROM_ADDR + offset) as *const u8;
+        for (i, byte) in data.iter_mut().enumerate() {
+            self.wait_busy();
+            *byte = unsafe { ptr::read_volatile(base.add(i)) };
+        }
+        Ok(())
+    }
+
+    /// Write `data` starting at `offset`.  In `WriteMode::Buffered` the
+    /// last page written may be held in the page buffer until `flush`.
+    pub fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), Error> {
+        self.check_range(offset, data.len())?;
+        if self.is_locked() {
+            return Err(Error::Locked);
+        }
+
+        self.nvm.nvm.intflag.write(|w| w.seesovf().set_bit());
+        let base = (SEEPROM_ADDR + offset) as *mut u8;
+        for (i, byte) in data.iter().enumerate() {
+            self.wait_busy();
+            unsafe { ptr::write_volatile(base.add(i), *byte) };
+        }
+        self.wait_busy();
+
+        if self.nvm.nvm.intflag.read().seesovf().bit_is_set() {
+            Err(Error::Overflow)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Commit the page buffer to the flash in `WriteMode::Buffered`
+    pub fn flush(&mut self) -> Result<(), Error> {
+        if self.nvm.nvm.seestat.read().load().bit_is_set() {
+            self.nvm.command(0, |w| w.cmd().seeflush())?;
+        }
+        self.wait_busy();
+        Ok(())
+    }
+}
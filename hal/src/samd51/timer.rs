@@ -1,8 +1,8 @@
 //! Working with timer counter hardware
 use crate::hal::timer::{CountDown, Periodic};
-use crate::target_device::tc0::COUNT16;
+use crate::target_device::tc0::{COUNT16, COUNT32, COUNT8};
 #[allow(unused)]
-use crate::target_device::{MCLK, TC2, TC3};
+use crate::target_device::{MCLK, TC0, TC1, TC2, TC3};
 
 // Only the G variants are missing these timers
 #[cfg(all(not(feature = "samd51g19a"), not(feature = "samd51g18a")))]
@@ -16,22 +16,21 @@ use void::Void;
 use cortex_m::asm::delay as cycle_delay;
 
 // Note:
-// TC3 + TC4 can be paired to make a 32-bit counter
-// TC5 + TC6 can be paired to make a 32-bit counter
+// TC0 + TC1 can be paired to make a 32-bit counter
+// TC2 + TC3 can be paired to make a 32-bit counter
+// TC4 + TC5 can be paired to make a 32-bit counter
 
 /// A generic hardware timer counter.
-/// The counters are exposed in 16-bit mode only.
-/// The hardware allows configuring the 8-bit mode
-/// and pairing up some instances to run in 32-bit
-/// mode, but that functionality is not currently
-/// exposed by this hal implementation.
+/// The counters run in 16-bit mode; use `into_8bit` for
+/// the 8-bit mode, or `TimerCounter32` to pair up two
+/// instances into a 32-bit counter.
 /// TimerCounter implements both the `Periodic` and
 /// the `CountDown` embedded_hal timer traits.
 /// Before a hardware timer can be used, it must first
 /// have a clock configured.
 pub struct TimerCounter<TC> {
-    freq: Hertz,
-    tc: TC,
+    pub(crate) freq: Hertz,
+    pub(crate) tc: TC,
 }
 
 /// This is a helper trait to make it easier to make most of the
@@ -41,6 +40,37 @@ pub trait Count16 {
     fn count_16(&self) -> &COUNT16;
 }
 
+/// The 8-bit counterpart of `Count16`
+pub trait Count8 {
+    fn count_8(&self) -> &COUNT8;
+}
+
+/// The 32-bit counterpart of `Count16`, implemented by the master
+/// instance of each pair
+pub trait Count32 {
+    fn count_32(&self) -> &COUNT32;
+}
+
+/// A hardware timer counter running in 8-bit mode, obtained with
+/// `TimerCounter::into_8bit`.
+/// The narrower counter halves the register footprint, at the cost of a
+/// much shorter range: at most 255 cycles of the prescaled clock.
+pub struct TimerCounter8<TC> {
+    pub(crate) freq: Hertz,
+    pub(crate) tc: TC,
+}
+
+/// Two hardware timer counters paired into a 32-bit counter.
+/// The even numbered instance is the master, which holds the counter
+/// and compare registers and raises the interrupts; the slave is
+/// unusable while paired.
+/// The 32-bit range allows long timeouts to run without prescaling.
+pub struct TimerCounter32<TC, SLAVE> {
+    pub(crate) freq: Hertz,
+    pub(crate) tc: TC,
+    pub(crate) slave: SLAVE,
+}
+
 impl<TC> Periodic for TimerCounter<TC> {}
 impl<TC> CountDown for TimerCounter<TC>
 where
@@ -53,6 +83,39 @@ where
         T: Into<Hertz>,
     {
         let params = TimerParams::new(timeout, self.freq.0);
+        self.configure(params);
+    }
+
+    fn wait(&mut self) -> nb::Result<(), Void> {
+        let count = self.tc.count_16();
+        if count.intflag.read().ovf().bit_is_set() {
+            // Writing a 1 clears the flag
+            count.intflag.modify(|_, w| w.ovf().set_bit());
+            Ok(())
+        } else {
+            Err(nb::Error::WouldBlock)
+        }
+    }
+}
+
+impl<TC> TimerCounter<TC>
+where
+    TC: Count16,
+{
+    /// Start the timer like `CountDown::start`, but return an error
+    /// instead of panicking when `timeout` is out of range.  Returns the
+    /// frequency that is actually achieved, which differs from `timeout`
+    /// when it does not divide the clock frequency.
+    pub fn try_start<T>(&mut self, timeout: T) -> Result<Hertz, Error>
+    where
+        T: Into<Hertz>,
+    {
+        let params = TimerParams::try_new(timeout, self.freq.0)?;
+        self.configure(params);
+        Ok(params.actual_freq(self.freq.0))
+    }
+
+    fn configure(&mut self, params: TimerParams) {
         let divider = params.divider;
         let cycles = params.cycles;
         let count = self.tc.count_16();
@@ -99,8 +162,54 @@ where
         });
     }
 
-    fn wait(&mut self) -> nb::Result<(), Void> {
+    /// Enable the interrupt generation for this hardware timer.
+    /// This method only sets the clock configuration to trigger
+    /// the interrupt; it does not configure the interrupt controller
+    /// or define an interrupt handler.
+    pub fn enable_interrupt(&mut self) {
+        self.tc.count_16().intenset.write(|w| w.ovf().set_bit());
+    }
+
+    /// Disables interrupt generation for this hardware timer.
+    /// This method only sets the clock configuration to prevent
+    /// triggering the interrupt; it does not configure the interrupt
+    /// controller.
+    pub fn disable_interrupt(&mut self) {
+        self.tc.count_16().intenclr.write(|w| w.ovf().set_bit());
+    }
+
+    /// Switch the counter to 8-bit mode
+    pub fn into_8bit(self) -> TimerCounter8<TC>
+    where
+        TC: Count8,
+    {
         let count = self.tc.count_16();
+        count.ctrla.modify(|_, w| w.enable().clear_bit());
+        while count.syncbusy.read().bits() != 0 {}
+        TimerCounter8 {
+            freq: self.freq,
+            tc: self.tc,
+        }
+    }
+}
+
+impl<TC> Periodic for TimerCounter8<TC> {}
+impl<TC> CountDown for TimerCounter8<TC>
+where
+    TC: Count8,
+{
+    type Time = Hertz;
+
+    fn start<T>(&mut self, timeout: T)
+    where
+        T: Into<Hertz>,
+    {
+        let params = TimerParams::new_8bit(timeout, self.freq.0);
+        self.configure(params);
+    }
+
+    fn wait(&mut self) -> nb::Result<(), Void> {
+        let count = self.tc.count_8();
         if count.intflag.read().ovf().bit_is_set() {
             // Writing a 1 clears the flag
             count.intflag.modify(|_, w| w.ovf().set_bit());
@@ -111,24 +220,220 @@ where
     }
 }
 
-impl<TC> TimerCounter<TC>
+impl<TC> TimerCounter8<TC>
 where
-    TC: Count16,
+    TC: Count8,
 {
+    /// Start the timer like `CountDown::start`, but return an error
+    /// instead of panicking when `timeout` is out of range.  Returns the
+    /// frequency that is actually achieved, which differs from `timeout`
+    /// when it does not divide the clock frequency.
+    pub fn try_start<T>(&mut self, timeout: T) -> Result<Hertz, Error>
+    where
+        T: Into<Hertz>,
+    {
+        let params = TimerParams::try_new_8bit(timeout, self.freq.0)?;
+        self.configure(params);
+        Ok(params.actual_freq(self.freq.0))
+    }
+
+    fn configure(&mut self, params: TimerParams) {
+        let count = self.tc.count_8();
+
+        // Disable the timer while we reconfigure it
+        count.ctrla.modify(|_, w| w.enable().clear_bit());
+        while count.syncbusy.read().bits() != 0 {}
+
+        count.ctrla.write(|w| w.swrst().set_bit());
+        while count.syncbusy.read().bits() != 0 {}
+        // the SVD erroneously marks swrst as write-only, so we
+        // need to manually read the bit here
+        while count.ctrla.read().bits() & 1 != 0 {}
+
+        // PER only exists in 8-bit mode, so select it before writing TOP
+        count.ctrla.write(|w| w.mode().count8());
+        while count.syncbusy.read().bits() != 0 {}
+
+        count.ctrlbset.write(|w| {
+            // Count up when the direction bit is zero
+            w.dir().clear_bit();
+            // Periodic
+            w.oneshot().clear_bit()
+        });
+
+        // Set TOP value for nfrq mode
+        count.per.write(|w| unsafe { w.per().bits(params.cycles as u8) });
+        while count.syncbusy.read().bits() != 0 {}
+
+        // Normal Frequency Waveform generation wraps at PER
+        count.wave.modify(|_, w| w.wavegen().nfrq());
+
+        count.ctrla.modify(|_, w| {
+            match params.divider {
+                1 => w.prescaler().div1(),
+                2 => w.prescaler().div2(),
+                4 => w.prescaler().div4(),
+                8 => w.prescaler().div8(),
+                16 => w.prescaler().div16(),
+                64 => w.prescaler().div64(),
+                256 => w.prescaler().div256(),
+                1024 => w.prescaler().div1024(),
+                _ => unreachable!(),
+            };
+            w.enable().set_bit()
+        });
+    }
+
     /// Enable the interrupt generation for this hardware timer.
     /// This method only sets the clock configuration to trigger
     /// the interrupt; it does not configure the interrupt controller
     /// or define an interrupt handler.
     pub fn enable_interrupt(&mut self) {
-        self.tc.count_16().intenset.write(|w| w.ovf().set_bit());
+        self.tc.count_8().intenset.write(|w| w.ovf().set_bit());
     }
 
     /// Disables interrupt generation for this hardware timer.
-    /// This method only sets the clock configuration to prevent
-    /// triggering the interrupt; it does not configure the interrupt
-    /// controller.
     pub fn disable_interrupt(&mut self) {
-        self.tc.count_16().intenclr.write(|w| w.ovf().set_bit());
+        self.tc.count_8().intenclr.write(|w| w.ovf().set_bit());
+    }
+
+    /// Return to the 16-bit mode
+    pub fn into_16bit(self) -> TimerCounter<TC>
+    where
+        TC: Count16,
+    {
+        let count = self.tc.count_8();
+        count.ctrla.modify(|_, w| w.enable().clear_bit());
+        while count.syncbusy.read().bits() != 0 {}
+        TimerCounter {
+            freq: self.freq,
+            tc: self.tc,
+        }
+    }
+}
+
+impl<TC, SLAVE> Periodic for TimerCounter32<TC, SLAVE> {}
+impl<TC, SLAVE> CountDown for TimerCounter32<TC, SLAVE>
+where
+    TC: Count32,
+{
+    type Time = Hertz;
+
+    fn start<T>(&mut self, timeout: T)
+    where
+        T: Into<Hertz>,
+    {
+        let params = TimerParams::new_32bit(timeout, self.freq.0);
+        self.configure(params);
+    }
+
+    fn wait(&mut self) -> nb::Result<(), Void> {
+        let count = self.tc.count_32();
+        if count.intflag.read().ovf().bit_is_set() {
+            // Writing a 1 clears the flag
+            count.intflag.modify(|_, w| w.ovf().set_bit());
+            Ok(())
+        } else {
+            Err(nb::Error::WouldBlock)
+        }
+    }
+}
+
+impl<TC, SLAVE> TimerCounter32<TC, SLAVE>
+where
+    TC: Count32,
+{
+    /// Start the timer like `CountDown::start`, but return an error
+    /// instead of panicking when `timeout` is out of range.  Returns the
+    /// frequency that is actually achieved, which differs from `timeout`
+    /// when it does not divide the clock frequency.
+    pub fn try_start<T>(&mut self, timeout: T) -> Result<Hertz, Error>
+    where
+        T: Into<Hertz>,
+    {
+        let params = TimerParams::try_new_32bit(timeout, self.freq.0)?;
+        self.configure(params);
+        Ok(params.actual_freq(self.freq.0))
+    }
+
+    fn configure(&mut self, params: TimerParams) {
+        let count = self.tc.count_32();
+
+        // Disable the timer while we reconfigure it
+        count.ctrla.modify(|_, w| w.enable().clear_bit());
+        while count.syncbusy.read().bits() != 0 {}
+
+        count.ctrla.write(|w| w.swrst().set_bit());
+        while count.syncbusy.read().bits() != 0 {}
+        // the SVD erroneously marks swrst as write-only, so we
+        // need to manually read the bit here
+        while count.ctrla.read().bits() & 1 != 0 {}
+
+        // Pair with the slave before writing the 32-bit TOP
+        count.ctrla.write(|w| w.mode().count32());
+        while count.syncbusy.read().bits() != 0 {}
+
+        count.ctrlbset.write(|w| {
+            // Count up when the direction bit is zero
+            w.dir().clear_bit();
+            // Periodic
+            w.oneshot().clear_bit()
+        });
+
+        // Set TOP value for mfrq mode
+        count.cc[0].write(|w| unsafe { w.cc().bits(params.cycles) });
+        while count.syncbusy.read().bits() != 0 {}
+
+        // Enable Match Frequency Waveform generation
+        count.wave.modify(|_, w| w.wavegen().mfrq());
+
+        count.ctrla.modify(|_, w| {
+            match params.divider {
+                1 => w.prescaler().div1(),
+                2 => w.prescaler().div2(),
+                4 => w.prescaler().div4(),
+                8 => w.prescaler().div8(),
+                16 => w.prescaler().div16(),
+                64 => w.prescaler().div64(),
+                256 => w.prescaler().div256(),
+                1024 => w.prescaler().div1024(),
+                _ => unreachable!(),
+            };
+            w.enable().set_bit()
+        });
+    }
+
+    /// Enable the interrupt generation for this hardware timer.
+    /// The interrupt is raised by the master instance.
+    /// This method only sets the clock configuration to trigger
+    /// the interrupt; it does not configure the interrupt controller
+    /// or define an interrupt handler.
+    pub fn enable_interrupt(&mut self) {
+        self.tc.count_32().intenset.write(|w| w.ovf().set_bit());
+    }
+
+    /// Disables interrupt generation for this hardware timer.
+    pub fn disable_interrupt(&mut self) {
+        self.tc.count_32().intenclr.write(|w| w.ovf().set_bit());
+    }
+
+    /// Split the pair back into two 16-bit timer counters
+    pub fn free(self) -> (TimerCounter<TC>, TimerCounter<SLAVE>) {
+        let count = self.tc.count_32();
+        count.ctrla.modify(|_, w| w.enable().clear_bit());
+        while count.syncbusy.read().bits() != 0 {}
+        count.ctrla.write(|w| w.swrst().set_bit());
+        while count.syncbusy.read().bits() != 0 {}
+        (
+            TimerCounter {
+                freq: self.freq,
+                tc: self.tc,
+            },
+            TimerCounter {
+                freq: self.freq,
+                tc: self.slave,
+            },
+        )
     }
 }
 
@@ -143,6 +448,12 @@ impl Count16 for $TC {
     }
 }
 
+impl Count8 for $TC {
+    fn count_8(&self) -> &COUNT8 {
+        self.count8()
+    }
+}
+
 impl TimerCounter<$TC>
 {
     /// Configure this timer counter instance.
@@ -171,6 +482,48 @@ impl TimerCounter<$TC>
     }
 }
 
+macro_rules! tc32 {
+    ($($TYPE:ident: ($TC:ident, $SLAVE:ident),)+) => {
+        $(
+pub type $TYPE = TimerCounter32<$TC, $SLAVE>;
+
+impl Count32 for $TC {
+    fn count_32(&self) -> &COUNT32 {
+        self.count32()
+    }
+}
+
+impl TimerCounter32<$TC, $SLAVE>
+{
+    /// Pair up two timer counters into a 32-bit counter.
+    /// Both instances share a clock, which must be enabled
+    /// through their 16-bit constructors.
+    pub fn new(master: TimerCounter<$TC>, slave: TimerCounter<$SLAVE>) -> Self {
+        Self {
+            freq: master.freq,
+            tc: master.tc,
+            slave: slave.tc,
+        }
+    }
+}
+        )+
+    }
+}
+
+/// The prescaler dividers supported by the timer counters
+const DIVIDERS: [u16; 8] = [1, 2, 4, 8, 16, 64, 256, 1024];
+
+/// Errors that may occur configuring a timer counter
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error {
+    /// The frequency is too low for the counter, even with the largest
+    /// prescaler; `min` is the lowest achievable frequency
+    FrequencyTooLow { min: Hertz },
+    /// The frequency is higher than the clock of the counter; `max` is
+    /// the highest achievable frequency
+    FrequencyTooHigh { max: Hertz },
+}
+
 /// Helper type for computing cycles and divider given frequency
 #[derive(Debug, Clone, Copy)]
 pub struct TimerParams {
@@ -179,42 +532,103 @@ pub struct TimerParams {
 }
 
 impl TimerParams {
+    /// Compute the parameters of a 16-bit counter.
+    /// Panics if `timeout` is out of range; see `try_new`.
     pub fn new<T>(timeout: T, src_freq: u32) -> Self
     where
         T: Into<Hertz>,
     {
-        let timeout = timeout.into();
+        Self::with_width(timeout.into(), src_freq, 16)
+    }
+
+    /// Compute the parameters of an 8-bit counter.
+    /// Panics if `timeout` is out of range; see `try_new_8bit`.
+    pub fn new_8bit<T>(timeout: T, src_freq: u32) -> Self
+    where
+        T: Into<Hertz>,
+    {
+        Self::with_width(timeout.into(), src_freq, 8)
+    }
+
+    /// Compute the parameters of a 32-bit counter.
+    /// Panics if `timeout` is out of range; see `try_new_32bit`.
+    pub fn new_32bit<T>(timeout: T, src_freq: u32) -> Self
+    where
+        T: Into<Hertz>,
+    {
+        Self::with_width(timeout.into(), src_freq, 32)
+    }
+
+    /// Compute the parameters of a 16-bit counter, or return an error
+    /// with the achievable range if `timeout` is out of range
+    pub fn try_new<T>(timeout: T, src_freq: u32) -> Result<Self, Error>
+    where
+        T: Into<Hertz>,
+    {
+        Self::try_with_width(timeout.into(), src_freq, 16)
+    }
+
+    /// The 8-bit counterpart of `try_new`
+    pub fn try_new_8bit<T>(timeout: T, src_freq: u32) -> Result<Self, Error>
+    where
+        T: Into<Hertz>,
+    {
+        Self::try_with_width(timeout.into(), src_freq, 8)
+    }
+
+    /// The 32-bit counterpart of `try_new`
+    pub fn try_new_32bit<T>(timeout: T, src_freq: u32) -> Result<Self, Error>
+    where
+        T: Into<Hertz>,
+    {
+        Self::try_with_width(timeout.into(), src_freq, 32)
+    }
+
+    /// Returns the frequency produced by these parameters, which
+    /// differs from the requested one when the clock frequency is not a
+    /// multiple of it.  The counter wraps at `cycles` and counts one more
+    /// tick back to zero, so a period lasts `cycles + 1` ticks.
+    pub fn actual_freq(&self, src_freq: u32) -> Hertz {
+        Hertz((src_freq as u64 / self.divider as u64 / (self.cycles as u64 + 1)) as u32)
+    }
+
+    fn with_width(timeout: Hertz, src_freq: u32, bits: u32) -> Self {
+        match Self::try_with_width(timeout, src_freq, bits) {
+            Ok(params) => params,
+            Err(err) => panic!(
+                "timeout={} is out of range for a {} bit counter: {:?}",
+                timeout.0, bits, err
+            ),
+        }
+    }
+
+    fn try_with_width(timeout: Hertz, src_freq: u32, bits: u32) -> Result<Self, Error> {
+        let max_cycles = ((1u64 << bits) - 1) as u32;
         let ticks: u32 = src_freq / timeout.0.max(1);
-        let divider = ((ticks >> 16) + 1).next_power_of_two();
-        let divider = match divider {
-            1 | 2 | 4 | 8 | 16 | 64 | 256 | 1024 => divider,
-            // There are a couple of gaps, so we round up to the next largest
-            // divider; we'll need to count twice as many but it will work.
-            32 => 64,
-            128 => 256,
-            512 => 1024,
-            // Catch all case; this is lame.  Would be great to detect this
-            // and fail at compile time.
-            _ => 1024,
-        };
-
-        let cycles: u32 = ticks / divider as u32;
-
-        if cycles > u16::max_value() as u32 {
-            panic!(
-                "cycles {} is out of range for a 16 bit counter (timeout={})",
-                cycles, timeout.0
-            );
+        if ticks == 0 {
+            return Err(Error::FrequencyTooHigh {
+                max: Hertz(src_freq),
+            });
         }
 
-        TimerParams {
-            divider: divider as u16,
-            cycles,
+        // Use the smallest divider that fits, for the best resolution
+        for &divider in DIVIDERS.iter() {
+            let cycles = ticks / divider as u32;
+            if cycles <= max_cycles {
+                return Ok(TimerParams { divider, cycles });
+            }
         }
+
+        let slowest = max_cycles as u64 * 1024;
+        Err(Error::FrequencyTooLow {
+            min: Hertz(((src_freq as u64 + slowest - 1) / slowest) as u32),
+        })
     }
 }
 
 tc! {
+    TimerCounter0: (TC0, tc0_, Tc0Tc1Clock, apbamask),
+    TimerCounter1: (TC1, tc1_, Tc0Tc1Clock, apbamask),
     TimerCounter2: (TC2, tc2_, Tc2Tc3Clock, apbbmask),
     TimerCounter3: (TC3, tc3_, Tc2Tc3Clock, apbbmask),
 }
@@ -226,6 +640,16 @@ tc! {
     TimerCounter5: (TC5, tc5_, Tc4Tc5Clock, apbcmask),
 }
 
+tc32! {
+    TimerCounter01: (TC0, TC1),
+    TimerCounter23: (TC2, TC3),
+}
+
+#[cfg(all(not(feature = "samd51g19a"), not(feature = "samd51g18a")))]
+tc32! {
+    TimerCounter45: (TC4, TC5),
+}
+
 #[derive(Clone, Copy)]
 pub struct SpinTimer {
     cycles: u32,
@@ -254,3 +678,38 @@ impl CountDown for SpinTimer {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn smallest_divider_that_fits() {
+        let params = TimerParams::try_new(Hertz(1000), 48_000_000).unwrap();
+        assert_eq!((params.divider, params.cycles), (1, 48_000));
+        // The wrap back to zero takes one more tick
+        assert_eq!(params.actual_freq(48_000_000), Hertz(999));
+
+        let params = TimerParams::try_new(Hertz(10), 48_000_000).unwrap();
+        assert_eq!((params.divider, params.cycles), (256, 18_750));
+
+        let params = TimerParams::try_new_32bit(Hertz(1), 48_000_000).unwrap();
+        assert_eq!((params.divider, params.cycles), (1, 48_000_000));
+    }
+
+    #[test]
+    fn out_of_range() {
+        assert_eq!(
+            TimerParams::try_new(Hertz(48_000_001), 48_000_000).unwrap_err(),
+            Error::FrequencyTooHigh {
+                max: Hertz(48_000_000)
+            }
+        );
+        // 48MHz / (255 * 1024) is 183.8Hz
+        assert_eq!(
+            TimerParams::try_new_8bit(Hertz(1), 48_000_000).unwrap_err(),
+            Error::FrequencyTooLow { min: Hertz(184) }
+        );
+        assert!(TimerParams::try_new_8bit(Hertz(184), 48_000_000).is_ok());
+    }
+}